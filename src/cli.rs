@@ -0,0 +1,105 @@
+use std::path::PathBuf;
+
+use crate::game::map::MapDimensions;
+
+pub const HELP: &str = "\
+Usage: dink [OPTIONS]
+
+Options:
+  --seed <SEED>          Seed the random number generator, to play the same game again
+  --map-size <WxH>       Size of the map in tiles, such as 80x50
+  --generator <NAME>     Map generator to use: noise, caves or rooms
+  --config <FILE>        Read settings from a RON file instead of config.ron
+  --load <FILE>          Load a saved game
+  --replay <FILE>        Play back a recorded replay
+  --headless             Simulate the game without a window, with random player actions
+  --turns <N>            Number of player turns to simulate with --headless [default: 1000]
+  --window <WxH>         Size of the window in pixels, such as 1280x720
+  -h, --help             Print this help
+";
+
+// The number of player turns a headless game lasts if `--turns` isn't given
+const DEFAULT_HEADLESS_TURNS: u32 = 1000;
+
+/// The options given on the command line, each overriding the configuration file.
+#[derive(Debug, Default)]
+pub struct CliOptions {
+    pub seed: Option<u64>,
+    pub map_size: Option<MapDimensions>,
+    pub generator: Option<String>,
+    pub config: Option<PathBuf>,
+    pub load: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    // Number of player turns to simulate, if running without a window
+    pub headless_turns: Option<u32>,
+    pub window: Option<(u32, u32)>,
+    pub help: bool,
+}
+
+impl CliOptions {
+    /// Parse the arguments, not including the program name.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut options = CliOptions::default();
+        let mut is_headless = false;
+        let mut turns = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("{} must be followed by a value.", arg))
+            };
+            match arg.as_str() {
+                "--seed" => {
+                    options.seed = Some(
+                        value()?
+                            .parse()
+                            .map_err(|_| "--seed must be an unsigned integer.".to_string())?,
+                    )
+                }
+                "--map-size" => options.map_size = Some(value()?.parse()?),
+                "--generator" => options.generator = Some(value()?),
+                "--config" => options.config = Some(value()?.into()),
+                "--load" => options.load = Some(value()?.into()),
+                "--replay" => options.replay = Some(value()?.into()),
+                "--headless" => is_headless = true,
+                "--turns" => {
+                    turns = Some(
+                        value()?
+                            .parse()
+                            .map_err(|_| "--turns must be an unsigned integer.".to_string())?,
+                    )
+                }
+                "--window" => options.window = Some(parse_window_size(&value()?)?),
+                "-h" | "--help" => options.help = true,
+                _ => return Err(format!("Unknown option {:?}.", arg)),
+            }
+        }
+
+        if turns.is_some() && !is_headless {
+            return Err("--turns can only be used with --headless.".to_string());
+        }
+        if is_headless {
+            options.headless_turns = Some(turns.unwrap_or(DEFAULT_HEADLESS_TURNS));
+        }
+        if options.seed.is_some() && options.replay.is_some() {
+            return Err("--seed can't be used with --replay, which has its own seed.".to_string());
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_window_size(size: &str) -> Result<(u32, u32), String> {
+    let sides: Vec<Option<u32>> = size
+        .split('x')
+        .map(|side| side.trim().parse().ok().filter(|&side| side > 0))
+        .collect();
+    match sides.as_slice() {
+        [Some(width), Some(height)] => Ok((*width, *height)),
+        _ => Err(format!(
+            "Invalid window size {:?}, expected WIDTHxHEIGHT such as \"1280x720\".",
+            size
+        )),
+    }
+}
@@ -0,0 +1,56 @@
+use bevy::prelude::*;
+
+use crate::game::components::{CombatStats, Equipment, Health, PlayerTag, Position};
+use crate::game::items::spawn_item;
+use crate::game::occupancy::Occupancy;
+
+/// An entity hurt another, by moving into it or with an item.
+pub struct AttackEvent {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub damage: i32,
+}
+
+/// An entity ran out of health and was removed from the game.
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub was_player: bool,
+}
+
+/// The damage dealt by one attack, which is never negative.
+pub fn damage(attacker: &CombatStats, target: &CombatStats) -> i32 {
+    (attacker.attack - target.defence).max(0)
+}
+
+#[allow(clippy::type_complexity)]
+pub fn remove_dead(
+    mut commands: Commands,
+    combatants_query: Query<(
+        Entity,
+        &Health,
+        &Position,
+        Option<&PlayerTag>,
+        Option<&Equipment>,
+    )>,
+    mut occupancy: ResMut<Occupancy>,
+    mut death_events: EventWriter<DeathEvent>,
+) {
+    for (entity, health, position, player, equipment) in combatants_query.iter() {
+        if health.current <= 0 {
+            // Free up the tile straight away
+            occupancy.remove(entity, *position);
+            commands.entity(entity).despawn();
+            // Leave behind whatever it was wearing or wielding
+            for item in equipment
+                .into_iter()
+                .flat_map(|equipment| equipment.slots.values())
+            {
+                spawn_item(&mut commands, item.clone(), *position);
+            }
+            death_events.send(DeathEvent {
+                entity,
+                was_player: player.is_some(),
+            });
+        }
+    }
+}
@@ -1,30 +1,186 @@
-#[derive(Clone, Copy)]
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::effects::Consumable;
+use crate::game::equipment::{EquipmentSlot, Equippable};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
 pub struct Position {
     pub x: u32,
     pub y: u32,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Drawable {
     pub texture_index: u16,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub enum Action {
     StayStill,
     North,
     South,
     West,
     East,
+    // Go down or up the stairs the entity is standing on
+    UseStairs,
+    // Pick up an item from the tile the entity is standing on
+    PickUp,
+    // Drop the item at this index of the entity's inventory
+    Drop(usize),
+    // Use up the item at this index of the entity's inventory, aimed at the target if it needs one
+    UseItem {
+        index: usize,
+        target: Option<Position>,
+    },
+    // Wear or wield the item at this index of the entity's inventory
+    Equip(usize),
+    // Put the item in this slot back in the entity's inventory
+    Unequip(EquipmentSlot),
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct GoingToTakeAction {
     pub action: Option<Action>,
 }
 
-pub struct HaveUpdatedTilemap(pub bool);
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Health {
+    pub current: i32,
+    pub max: i32,
+}
+
+impl Health {
+    pub fn new(max: i32) -> Self {
+        Health { current: max, max }
+    }
+}
+
+/// The combat stats used in fights, including any bonuses from equipment.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CombatStats {
+    pub attack: i32,
+    pub defence: i32,
+}
+
+/// The combat stats of an entity that can use equipment, before any bonuses.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BaseCombatStats {
+    pub attack: i32,
+    pub defence: i32,
+}
+
+/// Energy builds up over time, and an entity can act once it has enough.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Energy(pub i32);
+
+/// How much energy an entity gains each tick.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Speed(pub i32);
+
+/// Entities with higher initiative act first when actions are resolved.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Initiative(pub i32);
+
+/// The tiles an entity can currently see.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Viewshed {
+    pub range: u32,
+    pub visible_tiles: HashSet<Position>,
+    // Whether the visible tiles need to be recomputed, e.g. after moving
+    pub is_dirty: bool,
+}
+
+impl Viewshed {
+    pub fn new(range: u32) -> Self {
+        Viewshed {
+            range,
+            visible_tiles: HashSet::new(),
+            is_dirty: true,
+        }
+    }
+}
+
+/// The tiles of the current map that an entity has seen at some point.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MapMemory {
+    pub width: u32,
+    pub height: u32,
+    explored_tiles: Vec<bool>,
+}
+
+impl MapMemory {
+    pub fn new(width: u32, height: u32) -> Self {
+        MapMemory {
+            width,
+            height,
+            explored_tiles: vec![false; (width * height) as usize],
+        }
+    }
+
+    pub fn is_explored(&self, position: Position) -> bool {
+        self.explored_tiles[(position.y * self.width + position.x) as usize]
+    }
+
+    pub fn explore(&mut self, position: Position) {
+        self.explored_tiles[(position.y * self.width + position.x) as usize] = true;
+    }
+}
+
+/// Something that can be picked up and carried.
+///
+/// Items on the floor are entities with a position, while carried items only exist in an
+/// inventory.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Item {
+    pub name: String,
+    pub texture_index: u16,
+    // Items that can't be used have no consumable effects
+    #[serde(default)]
+    pub consumable: Option<Consumable>,
+    // Items that can't be worn or wielded have no equipment slot
+    #[serde(default)]
+    pub equippable: Option<Equippable>,
+}
+
+/// The items an entity is carrying.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Inventory {
+    pub items: Vec<Item>,
+    pub capacity: usize,
+}
+
+impl Inventory {
+    pub fn new(capacity: usize) -> Self {
+        Inventory {
+            items: Vec::new(),
+            capacity,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.items.len() >= self.capacity
+    }
+}
+
+/// The items an entity is wearing or wielding, at most one in each slot.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Equipment {
+    pub slots: BTreeMap<EquipmentSlot, Item>,
+}
+
+/// What an entity is called in messages.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DisplayName(pub String);
+
+/// Other entities can't move onto the same tile.
+pub struct BlocksTile;
 
 pub struct PlayerTag;
 
 pub struct EnemyTag;
 
 pub struct MoveRandomlyTag;
+
+pub struct ChasePlayerTag;
@@ -1,17 +1,74 @@
 // Tilemap
 pub const MAP_ID: u16 = 0;
+// Layers are drawn in order of their IDs
 pub const WALLS_LAYER_ID: u16 = 0;
-pub const OBJECTS_LAYER_ID: u16 = 1;
+pub const REMEMBERED_WALLS_LAYER_ID: u16 = 1;
+pub const OBJECTS_LAYER_ID: u16 = 2;
+pub const TARGETING_LAYER_ID: u16 = 3;
+pub const BLOCKED_TARGETING_LAYER_ID: u16 = 4;
 pub const CHUNK_SIZE: u32 = 8;
 pub const TILE_SIZE: f32 = 32_f32;
-pub const N_CHUNKS_X: u32 = 3;
-pub const N_CHUNKS_Y: u32 = 3;
+pub const DEFAULT_MAP_WIDTH: u32 = 24;
+pub const DEFAULT_MAP_HEIGHT: u32 = 24;
 
 // Texture indices
-pub const N_TEXTURES: i32 = 3;
+pub const N_TEXTURES: i32 = 11;
 pub const WALL_TEXTURE_INDEX: u16 = 0;
 pub const PLAYER_TEXTURE_INDEX: u16 = 1;
 pub const ENEMY_TEXTURE_INDEX: u16 = 2;
+pub const DOWN_STAIRS_TEXTURE_INDEX: u16 = 3;
+pub const UP_STAIRS_TEXTURE_INDEX: u16 = 4;
+pub const POTION_TEXTURE_INDEX: u16 = 5;
+pub const SCROLL_TEXTURE_INDEX: u16 = 6;
+pub const TARGET_TEXTURE_INDEX: u16 = 7;
+pub const WEAPON_TEXTURE_INDEX: u16 = 8;
+pub const ARMOUR_TEXTURE_INDEX: u16 = 9;
+pub const RING_TEXTURE_INDEX: u16 = 10;
+
+// Combat
+pub const PLAYER_MAX_HEALTH: i32 = 30;
+pub const PLAYER_ATTACK: i32 = 5;
+pub const PLAYER_DEFENCE: i32 = 2;
+
+// Energy
+pub const ACTION_ENERGY_THRESHOLD: i32 = 100;
+pub const MOVE_ENERGY_COST: i32 = 100;
+pub const STAY_STILL_ENERGY_COST: i32 = 100;
+pub const USE_STAIRS_ENERGY_COST: i32 = 100;
+pub const PICK_UP_ENERGY_COST: i32 = 100;
+pub const DROP_ENERGY_COST: i32 = 100;
+pub const USE_ITEM_ENERGY_COST: i32 = 100;
+pub const EQUIP_ENERGY_COST: i32 = 100;
+pub const PLAYER_SPEED: i32 = 10;
+
+// Initiative
+pub const PLAYER_INITIATIVE: i32 = 10;
+pub const ENEMY_INITIATIVE: i32 = 10;
+
+// Enemies
+pub const ENEMY_COUNT: usize = 9;
+pub const ENEMY_MIN_DISTANCE_FROM_PLAYER: u32 = 6;
+pub const ENEMY_MAX_DENSITY: f64 = 0.05;
+pub const ENEMY_SPAWN_ATTEMPTS: u32 = 1000;
+
+// Items
+pub const ITEM_COUNT: usize = 6;
+pub const ITEM_SPAWN_ATTEMPTS: u32 = 1000;
+pub const PLAYER_INVENTORY_CAPACITY: usize = 10;
+
+// Field of view
+pub const PLAYER_SIGHT_RANGE: u32 = 8;
+pub const OUT_OF_SIGHT_BRIGHTNESS: f32 = 0.4;
 
 // Worldgen
-pub const WORLDGEN_SCALE: f64 = 3.0;
+pub const DEFAULT_GENERATOR: &str = "noise";
+// Number of tiles spanned by one unit of the noise function
+pub const NOISE_SCALE: f64 = 12.0;
+pub const NOISE_WALL_THRESHOLD: f64 = 0.0;
+pub const CAVE_FILL_PROBABILITY: f64 = 0.45;
+pub const CAVE_SMOOTHING_PASSES: u32 = 4;
+pub const CAVE_BIRTH_LIMIT: u32 = 5;
+pub const CAVE_SURVIVAL_LIMIT: u32 = 4;
+// Leaves must leave room for the smallest room and a wall on each side
+pub const BSP_MIN_LEAF_SIZE: u32 = 8;
+pub const BSP_MIN_ROOM_SIZE: u32 = 4;
@@ -0,0 +1,259 @@
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::game::combat::AttackEvent;
+use crate::game::components::{
+    Action, BlocksTile, GoingToTakeAction, Health, Inventory, Item, MapMemory, PlayerTag, Position,
+    Viewshed,
+};
+use crate::game::items::{ItemEvent, ItemEventKind};
+use crate::game::map::WorldMap;
+use crate::game::messages::{MessageCategory, MessageEvent};
+use crate::game::occupancy::Occupancy;
+use crate::game::pathfinding::manhattan_distance;
+use crate::game::rng::GameRng;
+
+/// What happens when an item is used up.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Consumable {
+    pub targeting: Targeting,
+    pub effects: Vec<Effect>,
+}
+
+/// Where an item's effects apply.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Targeting {
+    // Whoever uses the item
+    User,
+    // Every tile within the radius of a chosen tile, which must be visible and within range
+    Area { range: u32, radius: u32 },
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Effect {
+    // Restore health, up to the maximum
+    Heal { amount: i32 },
+    // Move to a random free tile of the level
+    Teleport,
+    // Explore the whole level
+    RevealMap,
+    // Hurt everything in the area, regardless of defence
+    Damage { amount: i32 },
+}
+
+impl Targeting {
+    /// Whether the tile can be targeted from where the user is standing.
+    pub fn is_valid_target(
+        &self,
+        user_position: Position,
+        viewshed: &Viewshed,
+        target: Position,
+    ) -> bool {
+        match self {
+            Targeting::User => true,
+            Targeting::Area { range, .. } => {
+                manhattan_distance(user_position, target) <= *range
+                    && viewshed.visible_tiles.contains(&target)
+            }
+        }
+    }
+
+    /// The tiles affected when the given tile is targeted.
+    pub fn affected_tiles(
+        &self,
+        world_map: &WorldMap,
+        user_position: Position,
+        target: Option<Position>,
+    ) -> Vec<Position> {
+        match (self, target) {
+            (Targeting::Area { radius, .. }, Some(target)) => {
+                let radius = *radius as i32;
+                let mut tiles = Vec::new();
+                for dx in -radius..=radius {
+                    for dy in -radius..=radius {
+                        let position = IVec2::new(target.x as i32 + dx, target.y as i32 + dy);
+                        if dx.abs() + dy.abs() > radius || !world_map.is_walkable(position) {
+                            continue;
+                        }
+                        tiles.push(Position {
+                            x: position.x as u32,
+                            y: position.y as u32,
+                        });
+                    }
+                }
+                tiles
+            }
+            _ => vec![user_position],
+        }
+    }
+}
+
+/// Use up the items entities have chosen to use, applying their effects.
+///
+/// Items are used before anyone moves, so an area is hit where it was aimed.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn use_items(
+    mut users_query: Query<(
+        Entity,
+        &GoingToTakeAction,
+        &mut Inventory,
+        Option<&PlayerTag>,
+    )>,
+    mut bodies_query: Query<(
+        &mut Position,
+        Option<&BlocksTile>,
+        Option<&mut Viewshed>,
+        Option<&mut MapMemory>,
+    )>,
+    mut health_query: Query<&mut Health>,
+    world_map: Res<WorldMap>,
+    mut occupancy: ResMut<Occupancy>,
+    mut rng: ResMut<GameRng>,
+    mut attack_events: EventWriter<AttackEvent>,
+    mut item_events: EventWriter<ItemEvent>,
+    mut message_events: EventWriter<MessageEvent>,
+) {
+    // Take the items out of the inventories first, in a fixed order as teleporting uses the random
+    // number generator
+    let mut uses: Vec<(Entity, Item, Option<Position>)> = Vec::new();
+    for (entity, chosen_action, mut inventory, player) in users_query.iter_mut() {
+        let (index, target) = match chosen_action.action {
+            Some(Action::UseItem { index, target }) if index < inventory.items.len() => {
+                (index, target)
+            }
+            _ => continue,
+        };
+        let consumable = match &inventory.items[index].consumable {
+            Some(consumable) => consumable,
+            None => continue,
+        };
+
+        // Don't waste the item if the target can't be reached
+        let is_target_valid = match (bodies_query.get_mut(entity), target) {
+            (Ok((position, _, Some(viewshed), _)), Some(target)) => consumable
+                .targeting
+                .is_valid_target(*position, &viewshed, target),
+            _ => consumable.targeting == Targeting::User,
+        };
+        if !is_target_valid {
+            if player.is_some() {
+                message_events.send(MessageEvent::new(
+                    "That target is out of reach.",
+                    MessageCategory::Warning,
+                ));
+            }
+            continue;
+        }
+
+        let item = inventory.items.remove(index);
+        uses.push((entity, item, target));
+    }
+    uses.sort_by_key(|(entity, _, _)| entity.id());
+
+    for (user, item, target) in uses {
+        item_events.send(ItemEvent {
+            entity: user,
+            item_name: item.name.clone(),
+            kind: ItemEventKind::Used,
+        });
+        let consumable = item.consumable.unwrap();
+        let user_position = match bodies_query.get_mut(user) {
+            Ok((position, _, _, _)) => *position,
+            Err(_) => continue,
+        };
+        let affected_tiles = consumable
+            .targeting
+            .affected_tiles(&world_map, user_position, target);
+
+        for effect in consumable.effects {
+            match effect {
+                Effect::Heal { amount } => {
+                    if let Ok(mut health) = health_query.get_mut(user) {
+                        health.current = (health.current + amount).min(health.max);
+                    }
+                }
+                Effect::Teleport => {
+                    teleport(
+                        user,
+                        &mut bodies_query,
+                        &world_map,
+                        &mut occupancy,
+                        &mut rng,
+                    );
+                }
+                Effect::RevealMap => {
+                    if let Ok((_, _, viewshed, Some(mut map_memory))) = bodies_query.get_mut(user) {
+                        for x in 0..world_map.width {
+                            for y in 0..world_map.height {
+                                map_memory.explore(Position { x, y });
+                            }
+                        }
+                        // Redraw the map with everything that is now known
+                        if let Some(mut viewshed) = viewshed {
+                            viewshed.is_dirty = true;
+                        }
+                    }
+                }
+                Effect::Damage { amount } => {
+                    for position in affected_tiles.iter() {
+                        for &target in occupancy.entities_at(*position) {
+                            if let Ok(mut health) = health_query.get_mut(target) {
+                                if health.current <= 0 {
+                                    continue;
+                                }
+                                health.current -= amount;
+                                attack_events.send(AttackEvent {
+                                    attacker: user,
+                                    target,
+                                    damage: amount,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Move an entity to a random free tile.
+#[allow(clippy::type_complexity)]
+fn teleport(
+    entity: Entity,
+    bodies_query: &mut Query<(
+        &mut Position,
+        Option<&BlocksTile>,
+        Option<&mut Viewshed>,
+        Option<&mut MapMemory>,
+    )>,
+    world_map: &WorldMap,
+    occupancy: &mut Occupancy,
+    rng: &mut GameRng,
+) {
+    let (mut position, blocks_tile, viewshed, _) = match bodies_query.get_mut(entity) {
+        Ok(body) => body,
+        Err(_) => return,
+    };
+
+    let mut free_tiles = Vec::new();
+    for x in 0..world_map.width {
+        for y in 0..world_map.height {
+            let tile = Position { x, y };
+            if world_map.is_walkable(IVec2::new(x as i32, y as i32)) && !occupancy.is_blocked(tile)
+            {
+                free_tiles.push(tile);
+            }
+        }
+    }
+    let destination = match free_tiles.choose(&mut *rng) {
+        Some(&destination) => destination,
+        None => return,
+    };
+
+    occupancy.move_entity(entity, *position, destination, blocks_tile.is_some());
+    *position = destination;
+    if let Some(mut viewshed) = viewshed {
+        viewshed.is_dirty = true;
+    }
+}
@@ -1,45 +1,374 @@
+use std::collections::HashSet;
+
 use bevy::prelude::*;
-use bevy_ecs_tilemap::prelude::*;
-use rand::distributions::{Distribution, Uniform};
 use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 use crate::game::components::{
-    Action, Drawable, EnemyTag, GoingToTakeAction, MoveRandomlyTag, Position,
+    Action, BaseCombatStats, BlocksTile, ChasePlayerTag, CombatStats, DisplayName, Drawable,
+    EnemyTag, Energy, Equipment, GoingToTakeAction, Health, Initiative, Item, MoveRandomlyTag,
+    PlayerTag, Position, Speed, Viewshed,
 };
 use crate::game::config::{
-    CHUNK_SIZE, ENEMY_TEXTURE_INDEX, MAP_ID, N_CHUNKS_X, N_CHUNKS_Y, WALLS_LAYER_ID,
+    ARMOUR_TEXTURE_INDEX, ENEMY_COUNT, ENEMY_INITIATIVE, ENEMY_MAX_DENSITY,
+    ENEMY_MIN_DISTANCE_FROM_PLAYER, ENEMY_SPAWN_ATTEMPTS, ENEMY_TEXTURE_INDEX,
+    WEAPON_TEXTURE_INDEX,
 };
+use crate::game::equipment::EquipmentSlot;
+use crate::game::fov::field_of_view;
+use crate::game::items::equipment_item;
+use crate::game::map::{MapLayout, WorldMap};
+use crate::game::movement::action_towards;
+use crate::game::pathfinding::{a_star, manhattan_distance};
+use crate::game::rng::GameRng;
+use crate::game::scheduler::is_ready;
+use crate::game::worldgen::connectivity::connected_regions;
+
+/// How an enemy decides where to go.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Behaviour {
+    Wander,
+    ChasePlayer,
+}
+
+/// Everything needed to spawn one kind of enemy.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EnemyType {
+    pub name: String,
+    pub texture_index: u16,
+    pub max_health: i32,
+    pub attack: i32,
+    pub defence: i32,
+    pub speed: i32,
+    // Chasers only chase the player while they can see them
+    pub sight_range: u32,
+    pub behaviour: Behaviour,
+    // Items the enemy might be spawned with, equipped, and drop when it dies
+    #[serde(default)]
+    pub equipment: Vec<EquipmentChance>,
+}
+
+/// An item an enemy has a chance of being spawned with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EquipmentChance {
+    // Between 0 and 1
+    pub chance: f64,
+    pub item: Item,
+}
 
-pub fn add(mut commands: Commands, map_query: MapQuery) {
-    let mut rng = rand::thread_rng();
-    let random_x = Uniform::from(0..(CHUNK_SIZE * N_CHUNKS_X));
-    let random_y = Uniform::from(0..(CHUNK_SIZE * N_CHUNKS_Y));
-
-    for _ in 1..10 {
-        let x = random_x.sample(&mut rng);
-        let y = random_y.sample(&mut rng);
-
-        let position_is_empty = map_query
-            .get_tile_entity(UVec2::new(x, y), MAP_ID, WALLS_LAYER_ID)
-            .is_err();
-
-        if position_is_empty {
-            commands.spawn_bundle((
-                Position { x, y },
-                EnemyTag,
-                Drawable {
-                    texture_index: ENEMY_TEXTURE_INDEX,
+/// The enemy types that can be spawned, each with a relative chance of being picked.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpawnTable {
+    pub entries: Vec<SpawnTableEntry>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpawnTableEntry {
+    pub weight: u32,
+    pub enemy_type: EnemyType,
+}
+
+impl Default for SpawnTable {
+    fn default() -> Self {
+        SpawnTable {
+            entries: vec![
+                // Fast but feeble, and not interested in the player
+                SpawnTableEntry {
+                    weight: 4,
+                    enemy_type: EnemyType {
+                        name: "rat".to_string(),
+                        texture_index: ENEMY_TEXTURE_INDEX,
+                        max_health: 5,
+                        attack: 3,
+                        defence: 0,
+                        speed: 20,
+                        sight_range: 6,
+                        behaviour: Behaviour::Wander,
+                        equipment: Vec::new(),
+                    },
                 },
-                GoingToTakeAction { action: None },
-                MoveRandomlyTag,
-            ));
+                SpawnTableEntry {
+                    weight: 3,
+                    enemy_type: EnemyType {
+                        name: "goblin".to_string(),
+                        texture_index: ENEMY_TEXTURE_INDEX,
+                        max_health: 10,
+                        attack: 4,
+                        defence: 1,
+                        speed: 10,
+                        sight_range: 6,
+                        behaviour: Behaviour::ChasePlayer,
+                        equipment: vec![EquipmentChance {
+                            chance: 0.3,
+                            item: equipment_item(
+                                "dagger",
+                                WEAPON_TEXTURE_INDEX,
+                                EquipmentSlot::Weapon,
+                                2,
+                                0,
+                            ),
+                        }],
+                    },
+                },
+                // Slow and short sighted, but hits hard
+                SpawnTableEntry {
+                    weight: 1,
+                    enemy_type: EnemyType {
+                        name: "ogre".to_string(),
+                        texture_index: ENEMY_TEXTURE_INDEX,
+                        max_health: 20,
+                        attack: 7,
+                        defence: 2,
+                        speed: 5,
+                        sight_range: 4,
+                        behaviour: Behaviour::ChasePlayer,
+                        equipment: vec![EquipmentChance {
+                            chance: 0.5,
+                            item: equipment_item(
+                                "leather armour",
+                                ARMOUR_TEXTURE_INDEX,
+                                EquipmentSlot::Armour,
+                                0,
+                                1,
+                            ),
+                        }],
+                    },
+                },
+            ],
+        }
+    }
+}
+
+impl SpawnTable {
+    pub fn choose<R: Rng>(&self, rng: &mut R) -> &EnemyType {
+        &self
+            .entries
+            .choose_weighted(rng, |entry| entry.weight)
+            .expect("The spawn table should have an entry with a positive weight.")
+            .enemy_type
+    }
+}
+
+/// Where and how many enemies to spawn.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpawnSettings {
+    pub enemy_count: usize,
+    // Manhattan distance from the player's spawn point
+    pub min_distance_from_player: u32,
+    // Maximum number of enemies per floor tile of a region
+    pub max_density: f64,
+    pub max_attempts: u32,
+}
+
+impl Default for SpawnSettings {
+    fn default() -> Self {
+        SpawnSettings {
+            enemy_count: ENEMY_COUNT,
+            min_distance_from_player: ENEMY_MIN_DISTANCE_FROM_PLAYER,
+            max_density: ENEMY_MAX_DENSITY,
+            max_attempts: ENEMY_SPAWN_ATTEMPTS,
+        }
+    }
+}
+
+pub fn add(
+    mut commands: Commands,
+    world_map: Res<WorldMap>,
+    layout: Res<MapLayout>,
+    settings: Res<SpawnSettings>,
+    spawn_table: Res<SpawnTable>,
+    mut rng: ResMut<GameRng>,
+) {
+    spawn_enemies(
+        &mut commands,
+        &world_map,
+        &layout,
+        &settings,
+        &spawn_table,
+        &mut rng,
+    );
+}
+
+/// Spawn enemies on a newly generated level, away from where the player arrives.
+pub fn spawn_enemies(
+    commands: &mut Commands,
+    world_map: &WorldMap,
+    layout: &MapLayout,
+    settings: &SpawnSettings,
+    spawn_table: &SpawnTable,
+    rng: &mut GameRng,
+) {
+    let player_position = layout.spawn_points.first().copied();
+
+    // Treat the whole map as one region if the generator didn't split it up
+    let regions = if layout.regions.is_empty() {
+        connected_regions(world_map)
+    } else {
+        layout.regions.clone()
+    };
+    let capacities: Vec<usize> = regions
+        .iter()
+        .map(|region| (region.tiles.len() as f64 * settings.max_density).ceil() as usize)
+        .collect();
+    let mut counts = vec![0; regions.len()];
+    let mut occupied: HashSet<Position> = player_position.into_iter().collect();
+
+    let mut n_spawned = 0;
+    let mut n_attempts = 0;
+    while n_spawned < settings.enemy_count && n_attempts < settings.max_attempts {
+        n_attempts += 1;
+
+        // Pick a tile in a region that isn't full yet
+        let open_regions: Vec<usize> = (0..regions.len())
+            .filter(|&index| counts[index] < capacities[index])
+            .collect();
+        let region_index = match open_regions.choose(&mut *rng) {
+            Some(&region_index) => region_index,
+            None => break,
+        };
+        let position = *regions[region_index].tiles.choose(&mut *rng).unwrap();
+
+        let is_far_enough = player_position.map_or(true, |player_position| {
+            manhattan_distance(position, player_position) >= settings.min_distance_from_player
+        });
+        if world_map.is_wall(position.x, position.y)
+            || occupied.contains(&position)
+            || !is_far_enough
+        {
+            continue;
+        }
+
+        spawn(commands, spawn_table.choose(rng), position, rng);
+        occupied.insert(position);
+        counts[region_index] += 1;
+        n_spawned += 1;
+    }
+
+    if n_spawned < settings.enemy_count {
+        log::warn!(
+            "Only found room for {} of {} enemies.",
+            n_spawned,
+            settings.enemy_count
+        );
+    }
+}
+
+pub fn spawn(
+    commands: &mut Commands,
+    enemy_type: &EnemyType,
+    position: Position,
+    rng: &mut GameRng,
+) {
+    // Roll for each item the enemy might carry, a later item replaces an earlier one in its slot
+    let mut equipment = Equipment::default();
+    for equipment_chance in enemy_type.equipment.iter() {
+        if rng.gen_bool(equipment_chance.chance.clamp(0.0, 1.0)) {
+            if let Some(equippable) = &equipment_chance.item.equippable {
+                equipment
+                    .slots
+                    .insert(equippable.slot, equipment_chance.item.clone());
+            }
         }
     }
+
+    let mut enemy = commands.spawn_bundle((
+        position,
+        EnemyTag,
+        DisplayName(enemy_type.name.clone()),
+        Drawable {
+            texture_index: enemy_type.texture_index,
+        },
+        GoingToTakeAction { action: None },
+        BlocksTile,
+        Initiative(ENEMY_INITIATIVE),
+        Energy::default(),
+        Speed(enemy_type.speed),
+        Viewshed::new(enemy_type.sight_range),
+        Health::new(enemy_type.max_health),
+        BaseCombatStats {
+            attack: enemy_type.attack,
+            defence: enemy_type.defence,
+        },
+        // Recomputed with the bonuses from the enemy's equipment once it has been spawned
+        CombatStats {
+            attack: enemy_type.attack,
+            defence: enemy_type.defence,
+        },
+        equipment,
+    ));
+    match enemy_type.behaviour {
+        Behaviour::Wander => enemy.insert(MoveRandomlyTag),
+        Behaviour::ChasePlayer => enemy.insert(ChasePlayerTag),
+    };
 }
 
 pub fn choose_random_action(
-    mut entities_query: Query<(&Position, &mut GoingToTakeAction), With<MoveRandomlyTag>>,
+    mut entities_query: Query<(Entity, &Energy, &mut GoingToTakeAction), With<MoveRandomlyTag>>,
+    mut rng: ResMut<GameRng>,
 ) {
+    // Choose an action at random, only once per turn so the random number generator is used
+    // deterministically
+    let mut deciding: Vec<_> = entities_query
+        .iter_mut()
+        .filter(|(_, energy, action_to_take)| is_ready(energy) && action_to_take.action.is_none())
+        .collect();
+    // The query's order can change when entities are loaded or a level is restored, so draw in
+    // a fixed order
+    deciding.sort_by_key(|(entity, _, _)| entity.id());
+    for (_, _, mut action_to_take) in deciding {
+        action_to_take.action = Some(random_action(&mut *rng));
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn chase_player(
+    mut chasers_query: Query<
+        (
+            Entity,
+            &Position,
+            &Energy,
+            &mut Viewshed,
+            &mut GoingToTakeAction,
+        ),
+        With<ChasePlayerTag>,
+    >,
+    player_query: Query<&Position, With<PlayerTag>>,
+    world_map: Res<WorldMap>,
+    mut rng: ResMut<GameRng>,
+) {
+    let player_position = player_query.iter().next().copied();
+
+    // Chasers that can't see or reach the player wander at random, so decide in a fixed order
+    let mut deciding: Vec<_> = chasers_query
+        .iter_mut()
+        .filter(|(_, _, energy, _, action_to_take)| {
+            is_ready(energy) && action_to_take.action.is_none()
+        })
+        .collect();
+    deciding.sort_by_key(|(entity, _, _, _, _)| entity.id());
+
+    for (_, position, _, mut viewshed, mut action_to_take) in deciding {
+        // The chaser may have moved since what it can see was last worked out
+        if viewshed.is_dirty {
+            viewshed.visible_tiles = field_of_view(*position, viewshed.range, &world_map);
+            viewshed.is_dirty = false;
+        }
+
+        // Take the first step along the shortest path to the player, if it can see them
+        let next_step = player_position
+            .filter(|player_position| viewshed.visible_tiles.contains(player_position))
+            .and_then(|player_position| a_star(&world_map, *position, player_position))
+            .and_then(|path| path.get(1).copied());
+        action_to_take.action = Some(match next_step {
+            Some(next_step) => action_towards(*position, next_step),
+            // Wander around if the player can't be seen or reached
+            None => random_action(&mut *rng),
+        });
+    }
+}
+
+pub fn random_action<R: Rng>(rng: &mut R) -> Action {
     let possible_actions = [
         Action::StayStill,
         Action::North,
@@ -47,10 +376,5 @@ pub fn choose_random_action(
         Action::West,
         Action::East,
     ];
-
-    for (_, mut action_to_take) in entities_query.iter_mut() {
-        // Choose an action at random
-        let action = possible_actions.choose(&mut rand::thread_rng()).unwrap();
-        action_to_take.action = Some(*action);
-    }
+    *possible_actions.choose(rng).unwrap()
 }
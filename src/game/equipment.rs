@@ -0,0 +1,108 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::{
+    Action, BaseCombatStats, CombatStats, Equipment, GoingToTakeAction, Inventory, PlayerTag,
+};
+use crate::game::items::{ItemEvent, ItemEventKind};
+use crate::game::messages::{MessageCategory, MessageEvent};
+
+/// Where an item is worn or wielded, each entity can only use one item in each slot.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub enum EquipmentSlot {
+    Weapon,
+    Armour,
+    Ring,
+}
+
+/// How an item changes the combat stats of whoever has it equipped.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Equippable {
+    pub slot: EquipmentSlot,
+    #[serde(default)]
+    pub attack_bonus: i32,
+    #[serde(default)]
+    pub defence_bonus: i32,
+}
+
+/// Move items between inventories and equipment slots.
+///
+/// Equipping an item puts whatever was in its slot back in the inventory. This runs before the
+/// actions are reset, as part of resolving them.
+pub fn equip_and_unequip(
+    mut wearers_query: Query<(
+        Entity,
+        &GoingToTakeAction,
+        &mut Inventory,
+        &mut Equipment,
+        Option<&PlayerTag>,
+    )>,
+    mut item_events: EventWriter<ItemEvent>,
+    mut message_events: EventWriter<MessageEvent>,
+) {
+    for (entity, chosen_action, mut inventory, mut equipment, player) in wearers_query.iter_mut() {
+        match chosen_action.action {
+            Some(Action::Equip(index)) if index < inventory.items.len() => {
+                let slot = match &inventory.items[index].equippable {
+                    Some(equippable) => equippable.slot,
+                    None => continue,
+                };
+                let item = inventory.items.remove(index);
+                item_events.send(ItemEvent {
+                    entity,
+                    item_name: item.name.clone(),
+                    kind: ItemEventKind::Equipped,
+                });
+                if let Some(previous_item) = equipment.slots.insert(slot, item) {
+                    inventory.items.push(previous_item);
+                }
+            }
+            Some(Action::Unequip(slot)) => {
+                if !equipment.slots.contains_key(&slot) {
+                    continue;
+                }
+                if inventory.is_full() {
+                    if player.is_some() {
+                        message_events.send(MessageEvent::new(
+                            "Your inventory is full.",
+                            MessageCategory::Warning,
+                        ));
+                    }
+                    continue;
+                }
+                let item = equipment.slots.remove(&slot).unwrap();
+                item_events.send(ItemEvent {
+                    entity,
+                    item_name: item.name.clone(),
+                    kind: ItemEventKind::Unequipped,
+                });
+                inventory.items.push(item);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Recompute the combat stats of entities whose equipment has changed.
+#[allow(clippy::type_complexity)]
+pub fn update_combat_stats(
+    mut wearers_query: Query<
+        (&BaseCombatStats, &Equipment, &mut CombatStats),
+        Or<(Changed<Equipment>, Changed<BaseCombatStats>)>,
+    >,
+) {
+    for (base_stats, equipment, mut combat_stats) in wearers_query.iter_mut() {
+        let bonuses = equipment
+            .slots
+            .values()
+            .filter_map(|item| item.equippable.as_ref());
+        *combat_stats = CombatStats {
+            attack: base_stats.attack,
+            defence: base_stats.defence,
+        };
+        for equippable in bonuses {
+            combat_stats.attack += equippable.attack_bonus;
+            combat_stats.defence += equippable.defence_bonus;
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::game::components::{MapMemory, Position, Viewshed};
+use crate::game::map::WorldMap;
+
+pub fn update_viewsheds(
+    mut viewers_query: Query<(&Position, &mut Viewshed, Option<&mut MapMemory>)>,
+    world_map: Res<WorldMap>,
+) {
+    // A new or replaced map invalidates every viewshed
+    let has_map_changed = world_map.is_changed();
+
+    for (position, mut viewshed, map_memory) in viewers_query.iter_mut() {
+        if viewshed.is_dirty || has_map_changed {
+            viewshed.visible_tiles = field_of_view(*position, viewshed.range, &world_map);
+            viewshed.is_dirty = false;
+
+            // Remember everything that has been seen
+            if let Some(mut map_memory) = map_memory {
+                for visible_tile in viewshed.visible_tiles.iter() {
+                    map_memory.explore(*visible_tile);
+                }
+            }
+        }
+    }
+}
+
+/// The tiles visible from `origin`, using symmetric shadowcasting.
+///
+/// See <https://www.albertford.com/shadowcasting/> for a description of the algorithm.
+pub fn field_of_view(origin: Position, range: u32, world_map: &WorldMap) -> HashSet<Position> {
+    let mut visible_tiles = HashSet::new();
+    visible_tiles.insert(origin);
+
+    for quadrant in [
+        Quadrant::North,
+        Quadrant::East,
+        Quadrant::South,
+        Quadrant::West,
+    ] {
+        let mut scan = Scan {
+            origin,
+            quadrant,
+            range: range as i32,
+            world_map,
+            visible_tiles: &mut visible_tiles,
+        };
+        scan.row(Row {
+            depth: 1,
+            start_slope: Slope::new(-1, 1),
+            end_slope: Slope::new(1, 1),
+        });
+    }
+
+    visible_tiles
+}
+
+#[derive(Clone, Copy)]
+enum Quadrant {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Quadrant {
+    /// Convert a depth and column relative to the origin into a map position.
+    fn transform(&self, origin: Position, depth: i32, column: i32) -> IVec2 {
+        let origin = IVec2::new(origin.x as i32, origin.y as i32);
+        match self {
+            Quadrant::North => origin + IVec2::new(column, depth),
+            Quadrant::South => origin + IVec2::new(column, -depth),
+            Quadrant::East => origin + IVec2::new(depth, column),
+            Quadrant::West => origin + IVec2::new(-depth, column),
+        }
+    }
+}
+
+/// An exact fraction, so the symmetry of the algorithm isn't broken by rounding errors.
+#[derive(Clone, Copy)]
+struct Slope {
+    numerator: i32,
+    // Always positive
+    denominator: i32,
+}
+
+impl Slope {
+    fn new(numerator: i32, denominator: i32) -> Self {
+        Slope {
+            numerator,
+            denominator,
+        }
+    }
+
+    /// The slope of the edge of the tile nearest the start of the row.
+    fn of_tile(depth: i32, column: i32) -> Self {
+        Slope::new(2 * column - 1, 2 * depth)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Row {
+    depth: i32,
+    start_slope: Slope,
+    end_slope: Slope,
+}
+
+impl Row {
+    fn min_column(&self) -> i32 {
+        // Round depth * start_slope, with ties rounding up
+        let Slope {
+            numerator,
+            denominator,
+        } = self.start_slope;
+        (2 * self.depth * numerator + denominator).div_euclid(2 * denominator)
+    }
+
+    fn max_column(&self) -> i32 {
+        // Round depth * end_slope, with ties rounding down
+        let Slope {
+            numerator,
+            denominator,
+        } = self.end_slope;
+        -(denominator - 2 * self.depth * numerator).div_euclid(2 * denominator)
+    }
+
+    /// Whether a tile in the row can be seen from the origin, and the origin from the tile.
+    fn is_symmetric(&self, column: i32) -> bool {
+        column * self.start_slope.denominator >= self.depth * self.start_slope.numerator
+            && column * self.end_slope.denominator <= self.depth * self.end_slope.numerator
+    }
+
+    fn next(&self) -> Row {
+        Row {
+            depth: self.depth + 1,
+            ..*self
+        }
+    }
+}
+
+struct Scan<'a> {
+    origin: Position,
+    quadrant: Quadrant,
+    range: i32,
+    world_map: &'a WorldMap,
+    visible_tiles: &'a mut HashSet<Position>,
+}
+
+impl<'a> Scan<'a> {
+    fn row(&mut self, mut row: Row) {
+        if row.depth > self.range {
+            return;
+        }
+
+        let mut previous_is_wall = None;
+        for column in row.min_column()..=row.max_column() {
+            let is_wall = self.is_wall(row.depth, column);
+            if is_wall || row.is_symmetric(column) {
+                self.reveal(row.depth, column);
+            }
+            if previous_is_wall == Some(true) && !is_wall {
+                row.start_slope = Slope::of_tile(row.depth, column);
+            }
+            if previous_is_wall == Some(false) && is_wall {
+                let mut next_row = row.next();
+                next_row.end_slope = Slope::of_tile(row.depth, column);
+                self.row(next_row);
+            }
+            previous_is_wall = Some(is_wall);
+        }
+        if previous_is_wall == Some(false) {
+            self.row(row.next());
+        }
+    }
+
+    fn is_wall(&self, depth: i32, column: i32) -> bool {
+        // Anything outside the map blocks sight
+        let position = self.quadrant.transform(self.origin, depth, column);
+        !self.world_map.is_in_bounds(position)
+            || self.world_map.is_wall(position.x as u32, position.y as u32)
+    }
+
+    fn reveal(&mut self, depth: i32, column: i32) {
+        let position = self.quadrant.transform(self.origin, depth, column);
+        let is_in_range = depth * depth + column * column <= self.range * self.range;
+        if is_in_range && self.world_map.is_in_bounds(position) {
+            self.visible_tiles.insert(Position {
+                x: position.x as u32,
+                y: position.y as u32,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::map::TileKind;
+
+    #[test]
+    fn walls_block_sight() {
+        let world_map = WorldMap::from_rows(&[
+            "#########",
+            "#...#...#",
+            "#...#...#",
+            "#...#...#",
+            "#########",
+        ]);
+        let visible_tiles = field_of_view(Position { x: 2, y: 2 }, 10, &world_map);
+
+        assert!(visible_tiles.contains(&Position { x: 3, y: 2 }));
+        // The wall itself can be seen, but not what is behind it
+        assert!(visible_tiles.contains(&Position { x: 4, y: 2 }));
+        for x in 5..8 {
+            for y in 1..4 {
+                assert!(!visible_tiles.contains(&Position { x, y }));
+            }
+        }
+    }
+
+    #[test]
+    fn pillars_cast_shadows() {
+        let world_map = WorldMap::from_rows(&[
+            "...........",
+            "...........",
+            "...........",
+            ".....#.....",
+            "...........",
+            "...........",
+            "...........",
+        ]);
+        let origin = Position { x: 5, y: 0 };
+        let visible_tiles = field_of_view(origin, 10, &world_map);
+
+        assert!(visible_tiles.contains(&Position { x: 5, y: 3 }));
+        // Directly behind the pillar is hidden, while the tiles to the sides can still be seen
+        for y in 4..7 {
+            assert!(!visible_tiles.contains(&Position { x: 5, y }));
+        }
+        assert!(visible_tiles.contains(&Position { x: 3, y: 6 }));
+        assert!(visible_tiles.contains(&Position { x: 7, y: 6 }));
+    }
+
+    #[test]
+    fn sight_is_limited_by_range() {
+        let world_map = WorldMap::new(20, 20);
+        let visible_tiles = field_of_view(Position { x: 10, y: 10 }, 3, &world_map);
+
+        assert!(visible_tiles.contains(&Position { x: 13, y: 10 }));
+        assert!(!visible_tiles.contains(&Position { x: 14, y: 10 }));
+        assert!(!visible_tiles.contains(&Position { x: 13, y: 13 }));
+    }
+
+    #[test]
+    fn sight_is_symmetric() {
+        // Scatter pillars and short walls over an open map
+        let (width, height) = (16, 16);
+        let mut world_map = WorldMap::new(width, height);
+        for x in 0..width {
+            for y in 0..height {
+                if (x * 7 + y * 13) % 11 == 0 || (x == 8 && y > 3 && y < 9) {
+                    world_map.set(x, y, TileKind::Wall);
+                }
+            }
+        }
+
+        let range = 8;
+        let floor_tiles: Vec<Position> = (0..width)
+            .flat_map(|x| (0..height).map(move |y| Position { x, y }))
+            .filter(|position| !world_map.is_wall(position.x, position.y))
+            .collect();
+        let fields_of_view: Vec<HashSet<Position>> = floor_tiles
+            .iter()
+            .map(|&position| field_of_view(position, range, &world_map))
+            .collect();
+
+        for (a, a_sees) in floor_tiles.iter().zip(fields_of_view.iter()) {
+            for (b, b_sees) in floor_tiles.iter().zip(fields_of_view.iter()) {
+                assert_eq!(
+                    a_sees.contains(b),
+                    b_sees.contains(a),
+                    "{:?} and {:?} don't see each other the same way",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use bevy::app::AppExit;
+use bevy::prelude::*;
+
+use crate::game::components::{GoingToTakeAction, PlayerTag};
+use crate::game::enemy::random_action;
+use crate::game::replay::ReplayPlayback;
+use crate::game::rng::GameRng;
+use crate::game::states::{GameState, TurnCount};
+
+/// Run the game without a window, with the player choosing their actions at random unless a
+/// replay is being played back.
+///
+/// The app exits after the turn limit, when the player dies or when the replay runs out.
+pub struct HeadlessPlugin {
+    // The app exits once the player has taken this many turns
+    pub turns: u32,
+}
+
+pub struct TurnLimit(pub u32);
+
+/// Where the simulated player's random actions come from.
+///
+/// This is kept apart from the game's random number generator, so playing back the recorded
+/// actions, which doesn't draw any, leaves the game's own draws unchanged.
+pub struct PlayerRng(pub GameRng);
+
+// Mixed into the game's seed, so the player's draws don't mirror the game's
+const PLAYER_RNG_SEED_OFFSET: u64 = 0x9e37_79b9_7f4a_7c15;
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let seed = app
+            .world()
+            .get_resource::<GameRng>()
+            .expect("The game plugin should be added before the headless plugin.")
+            .seed();
+
+        app.insert_resource(TurnLimit(self.turns))
+            .insert_resource(PlayerRng(GameRng::from_seed(
+                seed.wrapping_add(PLAYER_RNG_SEED_OFFSET),
+            )))
+            .add_system_set(
+                SystemSet::on_update(GameState::PlayerTurn)
+                    .with_system(decide_random_action.system()),
+            )
+            // Check before the player's turn ends, otherwise the turn can be counted while the
+            // game still seems to wait for the player, and the run ends before it is resolved
+            .add_system(exit_after_turn_limit.system().before("end_player_turn"));
+    }
+}
+
+pub fn decide_random_action(
+    mut player_query: Query<&mut GoingToTakeAction, With<PlayerTag>>,
+    mut player_rng: ResMut<PlayerRng>,
+    turn_count: Res<TurnCount>,
+    turn_limit: Res<TurnLimit>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    // The replay chooses the player's actions instead
+    if playback.is_some() {
+        return;
+    }
+
+    // Stop acting at the turn limit, so the game waits for the player until the app exits
+    if turn_count.0 >= turn_limit.0 {
+        return;
+    }
+
+    for mut player_action in player_query.iter_mut() {
+        // Only choose once per turn, so the random number generator is used deterministically
+        if player_action.action.is_none() {
+            player_action.action = Some(random_action(&mut player_rng.0));
+        }
+    }
+}
+
+pub fn exit_after_turn_limit(
+    turn_count: Res<TurnCount>,
+    turn_limit: Res<TurnLimit>,
+    game_state: Res<State<GameState>>,
+    playback: Option<Res<ReplayPlayback>>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    // No more turns can be taken once the game is over
+    let is_game_over = game_state.current() == &GameState::GameOver;
+    // There is no way to unpause a finished replay without a window
+    let is_replay_finished = playback.map_or(false, |playback| playback.is_finished);
+    // The player's last turn has been resolved once the game is waiting for their next one
+    let is_waiting_for_player = game_state.current() == &GameState::PlayerTurn;
+    let is_turn_limit_reached = turn_count.0 >= turn_limit.0 && is_waiting_for_player;
+
+    if is_turn_limit_reached || is_game_over || is_replay_finished {
+        log::info!("Finished simulating {} turns.", turn_count.0);
+        app_exit_events.send(AppExit);
+    }
+}
@@ -20,16 +20,24 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use bevy::{prelude::*, render::camera::Camera};
+use bevy::{
+    prelude::*,
+    render::{camera::Camera, render_graph::base::camera::CAMERA_2D},
+};
 
 // A simple camera system for moving and zooming the camera.
 
 pub fn movement(
     time: Res<Time>,
     keyboard_input: Res<Input<KeyCode>>,
-    mut query: Query<&mut Transform, With<Camera>>,
+    mut query: Query<(&Camera, &mut Transform)>,
 ) {
-    for mut transform in query.iter_mut() {
+    for (camera, mut transform) in query.iter_mut() {
+        // Leave the UI camera where it is
+        if camera.name.as_deref() != Some(CAMERA_2D) {
+            continue;
+        }
+
         let mut direction = Vec3::ZERO;
         let scale = transform.scale.x;
 
@@ -0,0 +1,317 @@
+use bevy::prelude::*;
+
+use crate::game::components::{
+    Action, CombatStats, Equipment, GoingToTakeAction, Inventory, PlayerTag, Position, Viewshed,
+};
+use crate::game::effects::Targeting;
+use crate::game::equipment::EquipmentSlot;
+use crate::game::map::WorldMap;
+use crate::game::messages::{MessageCategory, MessageEvent};
+use crate::game::movement::action_delta;
+use crate::game::replay::ReplayPlayback;
+use crate::game::settings::KeyBindings;
+
+const FONT_SIZE: f32 = 20.0;
+const SELECTED_COLOR: Color = Color::YELLOW;
+
+/// Whether the inventory is shown, and which item in it is selected.
+///
+/// The items being carried are listed first, followed by the items equipped.
+#[derive(Default)]
+pub struct InventoryScreen {
+    pub is_open: bool,
+    pub selected: usize,
+    // The item being aimed, once it has been chosen from the inventory
+    pub aim: Option<Aim>,
+}
+
+/// An item from the inventory that is being aimed at a tile.
+pub struct Aim {
+    pub item_index: usize,
+    pub target: Position,
+}
+
+impl InventoryScreen {
+    /// Whether the keys are being used to browse the inventory or aim an item, rather than to act.
+    pub fn is_active(&self) -> bool {
+        self.is_open || self.aim.is_some()
+    }
+}
+
+pub struct InventoryPanel;
+
+pub struct InventoryText {
+    // The style each line starts from, before it is coloured by whether it's selected
+    style: TextStyle,
+}
+
+pub fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let style = TextStyle {
+        font: asset_server.load("fonts/DejaVuSansMono.ttf"),
+        font_size: FONT_SIZE,
+        color: Color::WHITE,
+    };
+
+    // A panel in the top right corner, hidden until the inventory is opened
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(16.0),
+                    right: Val::Px(16.0),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.8).into()),
+            ..Default::default()
+        })
+        .insert(InventoryPanel)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section("", style.clone(), Default::default()),
+                    ..Default::default()
+                })
+                .insert(InventoryText { style });
+        });
+}
+
+/// Open and close the inventory, and move the selection or drop, use, equip or unequip the
+/// selected item while it's open.
+///
+/// Items that are aimed close the inventory, then the keys move the target until the item is used,
+/// or the inventory is opened again to choose something else.
+#[allow(clippy::type_complexity)]
+pub fn browse_inventory(
+    keys: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut inventory_screen: ResMut<InventoryScreen>,
+    mut player_query: Query<
+        (
+            &Position,
+            &Viewshed,
+            &Inventory,
+            Option<&Equipment>,
+            &mut GoingToTakeAction,
+        ),
+        With<PlayerTag>,
+    >,
+    world_map: Res<WorldMap>,
+    mut message_events: EventWriter<MessageEvent>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    // The replay chooses the player's actions instead
+    if playback.is_some() {
+        return;
+    }
+    let (position, viewshed, inventory, equipment, mut player_action) =
+        match player_query.single_mut() {
+            Ok(player) => player,
+            Err(_) => return,
+        };
+    let n_items = inventory.items.len();
+    let equipped_slots: Vec<EquipmentSlot> = equipment
+        .map(|equipment| equipment.slots.keys().copied().collect())
+        .unwrap_or_default();
+
+    for key in keys.get_just_released() {
+        if let Some(aim) = &mut inventory_screen.aim {
+            let direction = if *key == key_bindings.north {
+                Some(Action::North)
+            } else if *key == key_bindings.south {
+                Some(Action::South)
+            } else if *key == key_bindings.west {
+                Some(Action::West)
+            } else if *key == key_bindings.east {
+                Some(Action::East)
+            } else {
+                None
+            };
+
+            if let Some(direction) = direction {
+                let target = IVec2::new(aim.target.x as i32, aim.target.y as i32)
+                    + action_delta(Some(direction));
+                if world_map.is_in_bounds(target) {
+                    aim.target = Position {
+                        x: target.x as u32,
+                        y: target.y as u32,
+                    };
+                }
+            } else if *key == key_bindings.use_item {
+                let targeting = inventory.items.get(aim.item_index).and_then(|item| {
+                    item.consumable
+                        .as_ref()
+                        .map(|consumable| consumable.targeting)
+                });
+                match targeting {
+                    Some(targeting)
+                        if targeting.is_valid_target(*position, viewshed, aim.target) =>
+                    {
+                        player_action.action = Some(Action::UseItem {
+                            index: aim.item_index,
+                            target: Some(aim.target),
+                        });
+                        inventory_screen.aim = None;
+                    }
+                    Some(_) => message_events.send(MessageEvent::new(
+                        "That target is out of reach.",
+                        MessageCategory::Warning,
+                    )),
+                    None => inventory_screen.aim = None,
+                }
+            } else if *key == key_bindings.inventory {
+                inventory_screen.aim = None;
+                inventory_screen.is_open = true;
+            }
+        } else if *key == key_bindings.inventory {
+            inventory_screen.is_open = !inventory_screen.is_open;
+        } else if !inventory_screen.is_open {
+            continue;
+        } else if *key == key_bindings.north {
+            inventory_screen.selected = inventory_screen.selected.saturating_sub(1);
+        } else if *key == key_bindings.south {
+            inventory_screen.selected += 1;
+        } else if *key == key_bindings.drop_item && inventory_screen.selected < n_items {
+            player_action.action = Some(Action::Drop(inventory_screen.selected));
+        } else if *key == key_bindings.use_item && inventory_screen.selected < n_items {
+            let item = &inventory.items[inventory_screen.selected];
+            match item
+                .consumable
+                .as_ref()
+                .map(|consumable| consumable.targeting)
+            {
+                Some(Targeting::User) => {
+                    player_action.action = Some(Action::UseItem {
+                        index: inventory_screen.selected,
+                        target: None,
+                    });
+                }
+                // Start aiming from where the player is standing
+                Some(Targeting::Area { .. }) => {
+                    inventory_screen.aim = Some(Aim {
+                        item_index: inventory_screen.selected,
+                        target: *position,
+                    });
+                    inventory_screen.is_open = false;
+                }
+                None if item.equippable.is_some() => {
+                    player_action.action = Some(Action::Equip(inventory_screen.selected));
+                }
+                None => message_events.send(MessageEvent::new(
+                    format!("The {} can't be used.", item.name),
+                    MessageCategory::Warning,
+                )),
+            }
+        } else if *key == key_bindings.use_item {
+            // Using an equipped item takes it off
+            if let Some(&slot) = equipped_slots.get(inventory_screen.selected - n_items) {
+                player_action.action = Some(Action::Unequip(slot));
+            }
+        }
+    }
+
+    // Keep the selection on an item, as items come and go
+    let last_index = (n_items + equipped_slots.len()).saturating_sub(1);
+    if inventory_screen.selected > last_index {
+        inventory_screen.selected = last_index;
+    }
+}
+
+/// Show or hide the inventory panel, listing what the player is carrying and wearing.
+#[allow(clippy::type_complexity)]
+pub fn update_inventory_screen(
+    inventory_screen: Res<InventoryScreen>,
+    player_query: Query<(&Inventory, Option<&Equipment>, &CombatStats), With<PlayerTag>>,
+    changed_inventory_query: Query<
+        (),
+        (
+            With<PlayerTag>,
+            Or<(Changed<Inventory>, Changed<Equipment>, Changed<CombatStats>)>,
+        ),
+    >,
+    mut panel_query: Query<&mut Style, With<InventoryPanel>>,
+    mut text_query: Query<(&mut Text, &InventoryText)>,
+) {
+    let has_inventory_changed = changed_inventory_query.iter().next().is_some();
+    if !inventory_screen.is_changed() && !has_inventory_changed {
+        return;
+    }
+
+    for mut style in panel_query.iter_mut() {
+        style.display = if inventory_screen.is_open {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+
+    let (inventory, equipment, combat_stats) = match player_query.iter().next() {
+        Some(player) => player,
+        None => return,
+    };
+    for (mut text, inventory_text) in text_query.iter_mut() {
+        let style = &inventory_text.style;
+        let line_color = |index: usize| {
+            if index == inventory_screen.selected {
+                SELECTED_COLOR
+            } else {
+                Color::WHITE
+            }
+        };
+
+        let mut lines = vec![
+            (
+                format!(
+                    "Attack {}, defence {}\n\n",
+                    combat_stats.attack, combat_stats.defence
+                ),
+                Color::WHITE,
+            ),
+            (
+                format!(
+                    "Inventory ({}/{})\n",
+                    inventory.items.len(),
+                    inventory.capacity
+                ),
+                Color::WHITE,
+            ),
+        ];
+        if inventory.items.is_empty() {
+            lines.push(("Nothing\n".to_string(), Color::WHITE));
+        }
+        for (index, item) in inventory.items.iter().enumerate() {
+            lines.push((format!("{}. {}\n", index + 1, item.name), line_color(index)));
+        }
+
+        lines.push(("\nEquipped\n".to_string(), Color::WHITE));
+        let equipped_items: Vec<_> = equipment
+            .map(|equipment| equipment.slots.iter().collect())
+            .unwrap_or_default();
+        if equipped_items.is_empty() {
+            lines.push(("Nothing\n".to_string(), Color::WHITE));
+        }
+        for (offset, (slot, item)) in equipped_items.into_iter().enumerate() {
+            let index = inventory.items.len() + offset;
+            lines.push((format!("{:?}: {}\n", slot, item.name), line_color(index)));
+        }
+
+        text.sections = lines
+            .into_iter()
+            .map(|(value, color)| TextSection {
+                value,
+                style: TextStyle {
+                    color,
+                    ..style.clone()
+                },
+            })
+            .collect();
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::{
+    Action, Drawable, GoingToTakeAction, Inventory, Item, PlayerTag, Position,
+};
+use crate::game::config::{
+    ARMOUR_TEXTURE_INDEX, ITEM_COUNT, ITEM_SPAWN_ATTEMPTS, POTION_TEXTURE_INDEX,
+    RING_TEXTURE_INDEX, SCROLL_TEXTURE_INDEX, WEAPON_TEXTURE_INDEX,
+};
+use crate::game::effects::{Consumable, Effect, Targeting};
+use crate::game::equipment::{EquipmentSlot, Equippable};
+use crate::game::map::{MapLayout, WorldMap};
+use crate::game::messages::{MessageCategory, MessageEvent};
+use crate::game::occupancy::Occupancy;
+use crate::game::rng::GameRng;
+use crate::game::worldgen::connectivity::connected_regions;
+
+/// The items that can be spawned, each with a relative chance of being picked.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ItemTable {
+    pub entries: Vec<ItemTableEntry>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ItemTableEntry {
+    pub weight: u32,
+    pub item: Item,
+}
+
+impl Default for ItemTable {
+    fn default() -> Self {
+        ItemTable {
+            entries: vec![
+                ItemTableEntry {
+                    weight: 4,
+                    item: Item {
+                        name: "healing potion".to_string(),
+                        texture_index: POTION_TEXTURE_INDEX,
+                        consumable: Some(Consumable {
+                            targeting: Targeting::User,
+                            effects: vec![Effect::Heal { amount: 10 }],
+                        }),
+                        equippable: None,
+                    },
+                },
+                ItemTableEntry {
+                    weight: 2,
+                    item: Item {
+                        name: "scroll of teleportation".to_string(),
+                        texture_index: SCROLL_TEXTURE_INDEX,
+                        consumable: Some(Consumable {
+                            targeting: Targeting::User,
+                            effects: vec![Effect::Teleport],
+                        }),
+                        equippable: None,
+                    },
+                },
+                ItemTableEntry {
+                    weight: 1,
+                    item: Item {
+                        name: "scroll of magic mapping".to_string(),
+                        texture_index: SCROLL_TEXTURE_INDEX,
+                        consumable: Some(Consumable {
+                            targeting: Targeting::User,
+                            effects: vec![Effect::RevealMap],
+                        }),
+                        equippable: None,
+                    },
+                },
+                // Hurts everything around where it lands, including the reader if they're too close
+                ItemTableEntry {
+                    weight: 2,
+                    item: Item {
+                        name: "scroll of fireball".to_string(),
+                        texture_index: SCROLL_TEXTURE_INDEX,
+                        consumable: Some(Consumable {
+                            targeting: Targeting::Area {
+                                range: 6,
+                                radius: 1,
+                            },
+                            effects: vec![Effect::Damage { amount: 8 }],
+                        }),
+                        equippable: None,
+                    },
+                },
+                ItemTableEntry {
+                    weight: 2,
+                    item: equipment_item(
+                        "dagger",
+                        WEAPON_TEXTURE_INDEX,
+                        EquipmentSlot::Weapon,
+                        2,
+                        0,
+                    ),
+                },
+                ItemTableEntry {
+                    weight: 1,
+                    item: equipment_item(
+                        "sword",
+                        WEAPON_TEXTURE_INDEX,
+                        EquipmentSlot::Weapon,
+                        4,
+                        0,
+                    ),
+                },
+                ItemTableEntry {
+                    weight: 2,
+                    item: equipment_item(
+                        "leather armour",
+                        ARMOUR_TEXTURE_INDEX,
+                        EquipmentSlot::Armour,
+                        0,
+                        1,
+                    ),
+                },
+                ItemTableEntry {
+                    weight: 1,
+                    item: equipment_item(
+                        "chain mail",
+                        ARMOUR_TEXTURE_INDEX,
+                        EquipmentSlot::Armour,
+                        0,
+                        3,
+                    ),
+                },
+                ItemTableEntry {
+                    weight: 1,
+                    item: equipment_item(
+                        "ring of strength",
+                        RING_TEXTURE_INDEX,
+                        EquipmentSlot::Ring,
+                        2,
+                        0,
+                    ),
+                },
+                ItemTableEntry {
+                    weight: 1,
+                    item: equipment_item(
+                        "ring of protection",
+                        RING_TEXTURE_INDEX,
+                        EquipmentSlot::Ring,
+                        0,
+                        2,
+                    ),
+                },
+            ],
+        }
+    }
+}
+
+/// An item that can be worn or wielded, but not used up.
+pub fn equipment_item(
+    name: &str,
+    texture_index: u16,
+    slot: EquipmentSlot,
+    attack_bonus: i32,
+    defence_bonus: i32,
+) -> Item {
+    Item {
+        name: name.to_string(),
+        texture_index,
+        consumable: None,
+        equippable: Some(Equippable {
+            slot,
+            attack_bonus,
+            defence_bonus,
+        }),
+    }
+}
+
+impl ItemTable {
+    pub fn choose<R: Rng>(&self, rng: &mut R) -> &Item {
+        &self
+            .entries
+            .choose_weighted(rng, |entry| entry.weight)
+            .expect("The item table should have an entry with a positive weight.")
+            .item
+    }
+}
+
+/// Something happened to an item an entity is carrying.
+pub struct ItemEvent {
+    pub entity: Entity,
+    pub item_name: String,
+    pub kind: ItemEventKind,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ItemEventKind {
+    PickedUp,
+    Dropped,
+    Used,
+    Equipped,
+    Unequipped,
+}
+
+/// How many items to leave lying around each level.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ItemSpawnSettings {
+    pub item_count: usize,
+    pub max_attempts: u32,
+}
+
+impl Default for ItemSpawnSettings {
+    fn default() -> Self {
+        ItemSpawnSettings {
+            item_count: ITEM_COUNT,
+            max_attempts: ITEM_SPAWN_ATTEMPTS,
+        }
+    }
+}
+
+pub fn add(
+    mut commands: Commands,
+    world_map: Res<WorldMap>,
+    layout: Res<MapLayout>,
+    settings: Res<ItemSpawnSettings>,
+    item_table: Res<ItemTable>,
+    mut rng: ResMut<GameRng>,
+) {
+    spawn_items(
+        &mut commands,
+        &world_map,
+        &layout,
+        &settings,
+        &item_table,
+        &mut rng,
+    );
+}
+
+/// Scatter items over the floor of a newly generated level, at most one per tile.
+pub fn spawn_items(
+    commands: &mut Commands,
+    world_map: &WorldMap,
+    layout: &MapLayout,
+    settings: &ItemSpawnSettings,
+    item_table: &ItemTable,
+    rng: &mut GameRng,
+) {
+    // Treat the whole map as one region if the generator didn't split it up
+    let regions = if layout.regions.is_empty() {
+        connected_regions(world_map)
+    } else {
+        layout.regions.clone()
+    };
+    let mut occupied = HashSet::new();
+
+    let mut n_spawned = 0;
+    let mut n_attempts = 0;
+    while n_spawned < settings.item_count && n_attempts < settings.max_attempts {
+        n_attempts += 1;
+
+        let region = match regions.choose(&mut *rng) {
+            Some(region) => region,
+            None => break,
+        };
+        let position = *region.tiles.choose(&mut *rng).unwrap();
+        if world_map.is_wall(position.x, position.y) || occupied.contains(&position) {
+            continue;
+        }
+
+        spawn_item(commands, item_table.choose(rng).clone(), position);
+        occupied.insert(position);
+        n_spawned += 1;
+    }
+
+    if n_spawned < settings.item_count {
+        log::warn!(
+            "Only found room for {} of {} items.",
+            n_spawned,
+            settings.item_count
+        );
+    }
+}
+
+/// Put an item on the floor.
+pub fn spawn_item(commands: &mut Commands, item: Item, position: Position) -> Entity {
+    commands
+        .spawn_bundle((
+            position,
+            Drawable {
+                texture_index: item.texture_index,
+            },
+            item,
+        ))
+        .id()
+}
+
+/// Move items between the floor and the inventories of the entities picking them up or dropping
+/// them.
+///
+/// This runs before the actions are reset, as part of resolving them.
+pub fn pick_up_and_drop(
+    mut commands: Commands,
+    mut carriers_query: Query<(
+        Entity,
+        &Position,
+        &GoingToTakeAction,
+        &mut Inventory,
+        Option<&PlayerTag>,
+    )>,
+    floor_items_query: Query<&Item, With<Position>>,
+    mut occupancy: ResMut<Occupancy>,
+    mut item_events: EventWriter<ItemEvent>,
+    mut message_events: EventWriter<MessageEvent>,
+) {
+    for (entity, position, chosen_action, mut inventory, player) in carriers_query.iter_mut() {
+        match chosen_action.action {
+            Some(Action::PickUp) => {
+                let item_entity = occupancy
+                    .entities_at(*position)
+                    .iter()
+                    .copied()
+                    .find(|&entity| floor_items_query.get(entity).is_ok());
+                let item_entity = match item_entity {
+                    Some(item_entity) => item_entity,
+                    None => continue,
+                };
+                if inventory.is_full() {
+                    if player.is_some() {
+                        message_events.send(MessageEvent::new(
+                            "Your inventory is full.",
+                            MessageCategory::Warning,
+                        ));
+                    }
+                    continue;
+                }
+
+                let item = floor_items_query.get(item_entity).unwrap().clone();
+                item_events.send(ItemEvent {
+                    entity,
+                    item_name: item.name.clone(),
+                    kind: ItemEventKind::PickedUp,
+                });
+                inventory.items.push(item);
+                // Take it off the tile straight away, so nothing else can pick it up this turn
+                occupancy.remove(item_entity, *position);
+                commands.entity(item_entity).despawn();
+            }
+            Some(Action::Drop(index)) if index < inventory.items.len() => {
+                let item = inventory.items.remove(index);
+                item_events.send(ItemEvent {
+                    entity,
+                    item_name: item.name.clone(),
+                    kind: ItemEventKind::Dropped,
+                });
+                spawn_item(&mut commands, item, *position);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether there is an item on the floor of the tile.
+pub fn is_item_at(
+    occupancy: &Occupancy,
+    floor_items_query: &Query<(), With<Item>>,
+    position: Position,
+) -> bool {
+    occupancy
+        .entities_at(position)
+        .iter()
+        .any(|&entity| floor_items_query.get(entity).is_ok())
+}
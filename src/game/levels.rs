@@ -0,0 +1,219 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::{Health, MapMemory, PlayerTag, Position, Viewshed};
+use crate::game::enemy::{spawn_enemies, SpawnSettings, SpawnTable};
+use crate::game::items::{spawn_items, ItemSpawnSettings, ItemTable};
+use crate::game::map::{MapDimensions, TileKind, WorldMap};
+use crate::game::rng::GameRng;
+use crate::game::save::{SavableComponents, SavedEntity};
+use crate::game::worldgen::connectivity::ConnectivityMode;
+use crate::game::worldgen::{generate_level, MapGenerators, SelectedGenerator, NEIGHBOUR_DELTAS};
+
+/// How far down the dungeon the player is, starting from 0 on the first level.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Depth(pub u32);
+
+/// A level the player has left, kept so it is exactly as they left it when they come back.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CachedLevel {
+    pub world_map: WorldMap,
+    // Everything on the level except the player
+    pub entities: Vec<SavedEntity>,
+    // What the player had explored of the level
+    pub map_memory: Option<MapMemory>,
+}
+
+/// Every level the player has visited, except the current one, by depth.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct LevelCache {
+    pub levels: BTreeMap<u32, CachedLevel>,
+}
+
+/// Sent when an entity uses the stairs it is standing on.
+pub struct UseStairsEvent {
+    pub entity: Entity,
+}
+
+/// Take the player to the level above or below when they use the stairs.
+///
+/// The level being left is cached, and the level being entered is restored from the cache if the
+/// player has been there before, or generated otherwise.
+#[allow(clippy::too_many_arguments)]
+pub fn change_level(
+    mut commands: Commands,
+    mut stairs_events: EventReader<UseStairsEvent>,
+    level_entities_query: Query<(Entity, SavableComponents), (With<Position>, Without<PlayerTag>)>,
+    mut player_query: Query<
+        (
+            &mut Position,
+            &mut Viewshed,
+            Option<&mut MapMemory>,
+            &Health,
+        ),
+        With<PlayerTag>,
+    >,
+    world_map: Res<WorldMap>,
+    dimensions: Res<MapDimensions>,
+    generators: Res<MapGenerators>,
+    selected: Res<SelectedGenerator>,
+    connectivity_mode: Res<ConnectivityMode>,
+    spawn_settings: Res<SpawnSettings>,
+    spawn_table: Res<SpawnTable>,
+    item_spawn_settings: Res<ItemSpawnSettings>,
+    item_table: Res<ItemTable>,
+    mut depth: ResMut<Depth>,
+    mut level_cache: ResMut<LevelCache>,
+    mut rng: ResMut<GameRng>,
+) {
+    // Only the player can change level
+    let player_entity = match stairs_events
+        .iter()
+        .map(|event| event.entity)
+        .find(|&entity| player_query.get_mut(entity).is_ok())
+    {
+        Some(entity) => entity,
+        None => return,
+    };
+    let (mut player_position, mut viewshed, map_memory, health) =
+        player_query.get_mut(player_entity).unwrap();
+    if health.current <= 0 {
+        return;
+    }
+
+    let new_depth = match world_map.get(player_position.x, player_position.y) {
+        TileKind::DownStairs => Depth(depth.0 + 1),
+        TileKind::UpStairs if depth.0 > 0 => Depth(depth.0 - 1),
+        _ => {
+            log::info!("There are no stairs here.");
+            return;
+        }
+    };
+
+    // Put the level being left away, leaving out anything that has just died
+    let entities = level_entities_query
+        .iter()
+        .filter(|(_, components)| {
+            let (_, _, (health, _, _), _, _, _) = components;
+            health.map_or(true, |health| health.current > 0)
+        })
+        .map(|(entity, components)| {
+            commands.entity(entity).despawn();
+            SavedEntity::from_components(components)
+        })
+        .collect();
+    level_cache.levels.insert(
+        depth.0,
+        CachedLevel {
+            world_map: world_map.clone(),
+            entities,
+            map_memory: map_memory.as_deref().cloned(),
+        },
+    );
+
+    // Arrive on the stairs leading back to where the player came from
+    let arrival_stairs = if new_depth.0 > depth.0 {
+        TileKind::UpStairs
+    } else {
+        TileKind::DownStairs
+    };
+    // Something may have been left standing on the stairs of a level being returned to. Enemies on
+    // new levels are spawned away from the player's spawn point, where the stairs up are.
+    let blocked_tiles: HashSet<Position> = level_cache
+        .levels
+        .get(&new_depth.0)
+        .into_iter()
+        .flat_map(|cached_level| cached_level.entities.iter())
+        .filter(|saved_entity| saved_entity.blocks_tile)
+        .filter_map(|saved_entity| saved_entity.position)
+        .collect();
+    let (new_world_map, new_map_memory) = match level_cache.levels.remove(&new_depth.0) {
+        Some(cached_level) => {
+            for saved_entity in cached_level.entities {
+                saved_entity.spawn(&mut commands);
+            }
+            (cached_level.world_map, cached_level.map_memory)
+        }
+        None => {
+            let generated_map = generate_level(
+                &generators,
+                &selected,
+                &dimensions,
+                *connectivity_mode,
+                new_depth,
+                &mut rng,
+            );
+            spawn_enemies(
+                &mut commands,
+                &generated_map.world_map,
+                &generated_map.layout,
+                &spawn_settings,
+                &spawn_table,
+                &mut rng,
+            );
+            spawn_items(
+                &mut commands,
+                &generated_map.world_map,
+                &generated_map.layout,
+                &item_spawn_settings,
+                &item_table,
+                &mut rng,
+            );
+            commands.insert_resource(generated_map.layout);
+            let map_memory = MapMemory::new(
+                generated_map.world_map.width,
+                generated_map.world_map.height,
+            );
+            (generated_map.world_map, Some(map_memory))
+        }
+    };
+
+    let stairs_position = new_world_map
+        .find(arrival_stairs)
+        .expect("Every level below the first should have stairs up and down.");
+    *player_position = nearest_free_tile(&new_world_map, stairs_position, &blocked_tiles);
+    viewshed.is_dirty = true;
+    if let (Some(mut map_memory), Some(new_map_memory)) = (map_memory, new_map_memory) {
+        *map_memory = new_map_memory;
+    }
+    commands.insert_resource(new_world_map);
+
+    // The message log reports the new depth
+    *depth = new_depth;
+}
+
+/// The closest walkable tile to a position that isn't blocked, found with a breadth first search,
+/// or the position itself if there is nowhere free.
+fn nearest_free_tile(
+    world_map: &WorldMap,
+    start: Position,
+    blocked_tiles: &HashSet<Position>,
+) -> Position {
+    let mut visited = HashSet::new();
+    let mut frontier = VecDeque::new();
+    visited.insert(start);
+    frontier.push_back(start);
+
+    while let Some(position) = frontier.pop_front() {
+        if !blocked_tiles.contains(&position) {
+            return position;
+        }
+        for delta in NEIGHBOUR_DELTAS.iter() {
+            let neighbour = IVec2::new(position.x as i32, position.y as i32) + *delta;
+            if !world_map.is_walkable(neighbour) {
+                continue;
+            }
+            let neighbour = Position {
+                x: neighbour.x as u32,
+                y: neighbour.y as u32,
+            };
+            if visited.insert(neighbour) {
+                frontier.push_back(neighbour);
+            }
+        }
+    }
+
+    start
+}
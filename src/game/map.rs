@@ -0,0 +1,202 @@
+use std::str::FromStr;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::Position;
+use crate::game::config::{CHUNK_SIZE, DEFAULT_MAP_HEIGHT, DEFAULT_MAP_WIDTH, TILE_SIZE};
+
+// Environment variable that can be used to choose the size of the map, such as "80x50"
+pub const MAP_SIZE_ENV_VAR: &str = "DINK_MAP_SIZE";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum TileKind {
+    Floor,
+    Wall,
+    // Leads to the next level down
+    DownStairs,
+    // Leads back to the previous level
+    UpStairs,
+}
+
+/// The size of the map, in tiles, and how it is split up for rendering.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MapDimensions {
+    pub width: u32,
+    pub height: u32,
+    pub chunk_size: u32,
+    pub tile_size: f32,
+}
+
+impl Default for MapDimensions {
+    fn default() -> Self {
+        MapDimensions::new(DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT)
+    }
+}
+
+impl FromStr for MapDimensions {
+    type Err = String;
+
+    /// Parse a size written as "WIDTHxHEIGHT".
+    fn from_str(size: &str) -> Result<Self, Self::Err> {
+        let parse_side = |side: Option<&str>| {
+            side.and_then(|side| side.trim().parse::<u32>().ok())
+                .filter(|&side| side > 0)
+        };
+        let mut sides = size.split('x');
+        match (
+            parse_side(sides.next()),
+            parse_side(sides.next()),
+            sides.next(),
+        ) {
+            (Some(width), Some(height), None) => Ok(MapDimensions::new(width, height)),
+            _ => Err(format!(
+                "Invalid map size {:?}, expected WIDTHxHEIGHT such as \"80x50\".",
+                size
+            )),
+        }
+    }
+}
+
+impl MapDimensions {
+    pub fn new(width: u32, height: u32) -> Self {
+        MapDimensions {
+            width,
+            height,
+            chunk_size: CHUNK_SIZE,
+            tile_size: TILE_SIZE,
+        }
+    }
+
+    /// Use the map size from the environment if one is set, otherwise the default one.
+    pub fn from_env_or_default() -> Self {
+        match std::env::var(MAP_SIZE_ENV_VAR) {
+            Ok(size) => size.parse().unwrap_or_else(|error| panic!("{}", error)),
+            Err(_) => MapDimensions::default(),
+        }
+    }
+
+    /// The number of chunks needed to cover the map, the last ones may be partly unused.
+    pub fn n_chunks(&self) -> UVec2 {
+        UVec2::new(
+            (self.width + self.chunk_size - 1) / self.chunk_size,
+            (self.height + self.chunk_size - 1) / self.chunk_size,
+        )
+    }
+}
+
+/// The gameplay copy of the map.
+///
+/// This is the source of truth for where the walls are; the tilemap layers only mirror it
+/// for rendering.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WorldMap {
+    pub width: u32,
+    pub height: u32,
+    tiles: Vec<TileKind>,
+}
+
+impl WorldMap {
+    pub fn new(width: u32, height: u32) -> Self {
+        WorldMap {
+            width,
+            height,
+            tiles: vec![TileKind::Floor; (width * height) as usize],
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    pub fn is_in_bounds(&self, position: IVec2) -> bool {
+        position.x >= 0
+            && position.y >= 0
+            && position.x < self.width as i32
+            && position.y < self.height as i32
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> TileKind {
+        self.tiles[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, kind: TileKind) {
+        let index = self.index(x, y);
+        self.tiles[index] = kind;
+    }
+
+    pub fn is_wall(&self, x: u32, y: u32) -> bool {
+        self.get(x, y) == TileKind::Wall
+    }
+
+    /// The first tile of a kind, scanning row by row.
+    pub fn find(&self, kind: TileKind) -> Option<Position> {
+        self.tiles
+            .iter()
+            .position(|&tile| tile == kind)
+            .map(|index| Position {
+                x: index as u32 % self.width,
+                y: index as u32 / self.width,
+            })
+    }
+
+    /// Whether an entity can stand at the position, out of bounds positions are never walkable.
+    pub fn is_walkable(&self, position: IVec2) -> bool {
+        self.is_in_bounds(position) && !self.is_wall(position.x as u32, position.y as u32)
+    }
+}
+
+/// A group of floor tiles that belong together, such as a room or a cave.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Region {
+    pub tiles: Vec<Position>,
+}
+
+impl Region {
+    /// The tile of the region closest to its centre of mass, so it is always inside the region
+    /// even if the region isn't convex.
+    pub fn centre(&self) -> Option<Position> {
+        let n_tiles = self.tiles.len() as u64;
+        if n_tiles == 0 {
+            return None;
+        }
+        let sum_x: u64 = self.tiles.iter().map(|position| position.x as u64).sum();
+        let sum_y: u64 = self.tiles.iter().map(|position| position.y as u64).sum();
+        let (centre_x, centre_y) = (sum_x as f64 / n_tiles as f64, sum_y as f64 / n_tiles as f64);
+
+        // Break ties by position so the choice doesn't depend on the order of the tiles
+        self.tiles.iter().copied().min_by(|a, b| {
+            let distance = |position: &Position| {
+                (position.x as f64 - centre_x).powi(2) + (position.y as f64 - centre_y).powi(2)
+            };
+            distance(a)
+                .partial_cmp(&distance(b))
+                .unwrap()
+                .then(a.cmp(b))
+        })
+    }
+}
+
+/// What the generator knows about the layout of the current map, besides its tiles.
+#[derive(Clone, Default)]
+pub struct MapLayout {
+    // Good places to start the player, best first
+    pub spawn_points: Vec<Position>,
+    pub regions: Vec<Region>,
+}
+
+#[cfg(test)]
+impl WorldMap {
+    /// Build a map from rows of `#` for walls and `.` for floor, the first row being `y = 0`.
+    pub fn from_rows(rows: &[&str]) -> Self {
+        let mut world_map = WorldMap::new(rows[0].len() as u32, rows.len() as u32);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, tile) in row.chars().enumerate() {
+                if tile == '#' {
+                    world_map.set(x as u32, y as u32, TileKind::Wall);
+                }
+            }
+        }
+        world_map
+    }
+}
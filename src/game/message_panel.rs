@@ -0,0 +1,151 @@
+use bevy::prelude::*;
+
+use crate::game::messages::MessageLog;
+use crate::game::settings::KeyBindings;
+
+const FONT_SIZE: f32 = 16.0;
+// Number of messages shown at once, normally and when looking through the history
+const RECENT_MESSAGE_COUNT: usize = 5;
+const HISTORY_MESSAGE_COUNT: usize = 25;
+
+/// Which part of the message log is shown.
+#[derive(Default)]
+pub struct MessagePanel {
+    // Show more messages at once, with the turn each was added on
+    pub is_history_open: bool,
+    // How many messages back from the newest the view has been scrolled
+    pub scroll: usize,
+}
+
+impl MessagePanel {
+    fn message_count(&self) -> usize {
+        if self.is_history_open {
+            HISTORY_MESSAGE_COUNT
+        } else {
+            RECENT_MESSAGE_COUNT
+        }
+    }
+}
+
+pub struct MessageText {
+    // The style each message starts from, before it is coloured by its category
+    style: TextStyle,
+}
+
+pub fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let style = TextStyle {
+        font: asset_server.load("fonts/DejaVuSansMono.ttf"),
+        font_size: FONT_SIZE,
+        color: Color::WHITE,
+    };
+
+    // A panel in the bottom left corner, with the newest message at the bottom
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(16.0),
+                    bottom: Val::Px(16.0),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.6).into()),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section("", style.clone(), Default::default()),
+                    ..Default::default()
+                })
+                .insert(MessageText { style });
+        });
+}
+
+/// Scroll through the messages, and open or close the full history.
+pub fn scroll_messages(
+    keys: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    message_log: Res<MessageLog>,
+    mut message_panel: ResMut<MessagePanel>,
+) {
+    for key in keys.get_just_released() {
+        if *key == key_bindings.message_history {
+            message_panel.is_history_open = !message_panel.is_history_open;
+            message_panel.scroll = 0;
+        } else if *key == key_bindings.scroll_messages_up {
+            message_panel.scroll += 1;
+        } else if *key == key_bindings.scroll_messages_down {
+            message_panel.scroll = message_panel.scroll.saturating_sub(1);
+        }
+    }
+
+    // Don't scroll past the oldest message
+    let max_scroll = message_log
+        .messages
+        .len()
+        .saturating_sub(message_panel.message_count());
+    if message_panel.scroll > max_scroll {
+        message_panel.scroll = max_scroll;
+    }
+}
+
+/// Show the messages in view, each in the colour of its category.
+pub fn update_message_panel(
+    message_panel: Res<MessagePanel>,
+    message_log: Res<MessageLog>,
+    mut text_query: Query<(&mut Text, &MessageText)>,
+) {
+    if !message_panel.is_changed() && !message_log.is_changed() {
+        return;
+    }
+
+    let n_messages = message_log.messages.len();
+    let end = n_messages.saturating_sub(message_panel.scroll);
+    let start = end.saturating_sub(message_panel.message_count());
+
+    for (mut text, message_text) in text_query.iter_mut() {
+        let style = &message_text.style;
+        let mut sections = Vec::new();
+        if message_panel.is_history_open {
+            sections.push(TextSection {
+                value: format!(
+                    "Messages {}-{} of {}\n",
+                    (start + 1).min(end),
+                    end,
+                    n_messages
+                ),
+                style: style.clone(),
+            });
+        }
+        for message in message_log.messages[start..end].iter() {
+            let value = if message_panel.is_history_open {
+                format!("{:>5} {}\n", message.turn, message.text)
+            } else {
+                format!("{}\n", message.text)
+            };
+            sections.push(TextSection {
+                value,
+                style: TextStyle {
+                    color: message.category.color(),
+                    ..style.clone()
+                },
+            });
+        }
+        // Text needs at least one section
+        if sections.is_empty() {
+            sections.push(TextSection {
+                value: String::new(),
+                style: style.clone(),
+            });
+        }
+        text.sections = sections;
+    }
+}
@@ -0,0 +1,196 @@
+use bevy::prelude::*;
+
+use crate::game::combat::{AttackEvent, DeathEvent};
+use crate::game::components::{DisplayName, PlayerTag};
+use crate::game::items::{ItemEvent, ItemEventKind};
+use crate::game::levels::Depth;
+use crate::game::movement::BlockedMoveEvent;
+use crate::game::states::TurnCount;
+
+/// What a message is about, which sets the colour it is shown in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MessageCategory {
+    Info,
+    // The player hurt something
+    Attack,
+    // Something hurt the player
+    Hurt,
+    Item,
+    Death,
+    // Something the player tried didn't work
+    Warning,
+}
+
+impl MessageCategory {
+    pub fn color(&self) -> Color {
+        match self {
+            MessageCategory::Info => Color::WHITE,
+            MessageCategory::Attack => Color::rgb(1.0, 0.65, 0.0),
+            MessageCategory::Hurt => Color::RED,
+            MessageCategory::Item => Color::CYAN,
+            MessageCategory::Death => Color::rgb(0.75, 0.4, 1.0),
+            MessageCategory::Warning => Color::GRAY,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub text: String,
+    pub category: MessageCategory,
+    // The turn the message was added on
+    pub turn: u32,
+}
+
+/// Everything that has been reported to the player, oldest first.
+#[derive(Default)]
+pub struct MessageLog {
+    pub messages: Vec<Message>,
+}
+
+/// Ask for a message to be added to the log, for things that aren't covered by another event.
+pub struct MessageEvent {
+    pub text: String,
+    pub category: MessageCategory,
+}
+
+impl MessageEvent {
+    pub fn new(text: impl Into<String>, category: MessageCategory) -> Self {
+        MessageEvent {
+            text: text.into(),
+            category,
+        }
+    }
+}
+
+/// Turn what happened in the game into messages for the player.
+///
+/// Only events involving the player are reported. Each message is also logged, so the game can be
+/// followed without a window.
+#[allow(clippy::too_many_arguments)]
+pub fn log_messages(
+    mut attack_events: EventReader<AttackEvent>,
+    mut death_events: EventReader<DeathEvent>,
+    mut blocked_move_events: EventReader<BlockedMoveEvent>,
+    mut item_events: EventReader<ItemEvent>,
+    mut message_events: EventReader<MessageEvent>,
+    names_query: Query<(Option<&DisplayName>, Option<&PlayerTag>)>,
+    depth: Res<Depth>,
+    turn_count: Res<TurnCount>,
+    mut message_log: ResMut<MessageLog>,
+    mut previous_depth: Local<Option<Depth>>,
+) {
+    let is_player = |entity: Entity| {
+        names_query
+            .get(entity)
+            .map_or(false, |(_, player)| player.is_some())
+    };
+    let name = |entity: Entity| {
+        if is_player(entity) {
+            return "you".to_string();
+        }
+        match names_query.get(entity) {
+            Ok((Some(name), _)) => format!("the {}", name.0),
+            _ => "something".to_string(),
+        }
+    };
+
+    let mut messages = Vec::new();
+    for attack in attack_events.iter() {
+        let (is_attacker_player, is_target_player) =
+            (is_player(attack.attacker), is_player(attack.target));
+        if !is_attacker_player && !is_target_player {
+            continue;
+        }
+        let (hit, does) = if is_attacker_player {
+            ("hit", "do")
+        } else {
+            ("hits", "does")
+        };
+        let text = if attack.damage > 0 {
+            format!(
+                "{} {} {} for {} damage.",
+                name(attack.attacker),
+                hit,
+                name(attack.target),
+                attack.damage
+            )
+        } else {
+            format!(
+                "{} {} {} but {} no damage.",
+                name(attack.attacker),
+                hit,
+                name(attack.target),
+                does
+            )
+        };
+        let category = if is_target_player {
+            MessageCategory::Hurt
+        } else {
+            MessageCategory::Attack
+        };
+        messages.push((capitalise(&text), category));
+    }
+    for death in death_events.iter() {
+        let text = if death.was_player {
+            "You die.".to_string()
+        } else {
+            capitalise(&format!("{} dies.", name(death.entity)))
+        };
+        messages.push((text, MessageCategory::Death));
+    }
+    for blocked_move in blocked_move_events.iter() {
+        if is_player(blocked_move.entity) {
+            messages.push((
+                "You bump into a wall.".to_string(),
+                MessageCategory::Warning,
+            ));
+        }
+    }
+    for item_event in item_events.iter() {
+        if !is_player(item_event.entity) {
+            continue;
+        }
+        let verb = match item_event.kind {
+            ItemEventKind::PickedUp => "pick up",
+            ItemEventKind::Dropped => "drop",
+            ItemEventKind::Used => "use",
+            ItemEventKind::Equipped => "equip",
+            ItemEventKind::Unequipped => "take off",
+        };
+        messages.push((
+            format!("You {} the {}.", verb, item_event.item_name),
+            MessageCategory::Item,
+        ));
+    }
+    for message in message_events.iter() {
+        messages.push((message.text.clone(), message.category));
+    }
+    // The depth changes when the player takes the stairs or a game is loaded
+    if *previous_depth != Some(*depth) {
+        if previous_depth.is_some() {
+            messages.push((
+                format!("You are now at depth {}.", depth.0),
+                MessageCategory::Info,
+            ));
+        }
+        *previous_depth = Some(*depth);
+    }
+
+    for (text, category) in messages {
+        log::info!("{}", text);
+        message_log.messages.push(Message {
+            text,
+            category,
+            turn: turn_count.0,
+        });
+    }
+}
+
+fn capitalise(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -1,62 +1,264 @@
 use bevy::prelude::*;
 
+pub mod combat;
 pub mod components;
 pub mod config;
+pub mod effects;
 pub mod enemy;
+pub mod equipment;
+pub mod fov;
+pub mod headless;
 pub mod helpers;
+pub mod inventory_screen;
+pub mod items;
+pub mod levels;
+pub mod map;
+pub mod message_panel;
+pub mod messages;
 pub mod movement;
+pub mod occupancy;
+pub mod pathfinding;
 pub mod player;
+pub mod replay;
+pub mod rng;
+pub mod save;
+pub mod scheduler;
+pub mod settings;
 pub mod setup;
 pub mod states;
 pub mod tilemap;
+pub mod worldgen;
 
+/// The gameplay logic, which doesn't need a window or a renderer.
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut AppBuilder) {
+        // Seed the game's random number generator, unless a seed has already been chosen
+        if app.world().get_resource::<rng::GameRng>().is_none() {
+            app.insert_resource(rng::GameRng::from_env_or_random());
+        }
+        let seed = app.world().get_resource::<rng::GameRng>().unwrap().seed();
+        log::info!("Using seed {}.", seed);
+
+        // Choose the size of the map, unless it has already been chosen
+        if app.world().get_resource::<map::MapDimensions>().is_none() {
+            app.insert_resource(map::MapDimensions::from_env_or_default());
+        }
+
+        // Choose how to generate the map, unless it has already been chosen
+        if app
+            .world()
+            .get_resource::<worldgen::SelectedGenerator>()
+            .is_none()
+        {
+            app.insert_resource(worldgen::SelectedGenerator::from_env_or_default());
+        }
+
+        // Use the default settings for anything that hasn't been configured
+        app.init_resource::<settings::WorldgenConfig>()
+            .init_resource::<enemy::SpawnSettings>()
+            .init_resource::<items::ItemSpawnSettings>();
+
+        // Record the run, unless a replay is being played back
+        if app
+            .world()
+            .get_resource::<replay::ReplayPlayback>()
+            .is_none()
+        {
+            let replay_settings = replay::ReplaySettings::from_world(app.world());
+            app.insert_resource(replay::ReplayRecorder {
+                path: replay::REPLAY_PATH.into(),
+                replay: replay::Replay::new(seed, replay_settings),
+                is_recording: true,
+            });
+        }
+
         app
-            // Create tilemap
-            .add_startup_system(setup::setup.system().label("worldgen"))
-            .add_system(helpers::texture::set_texture_filters_to_nearest.system())
-            // Add a camera
-            .add_startup_system(helpers::camera::add_camera.system())
-            .add_system(helpers::camera::movement.system())
-            // Add initial objects
-            .add_startup_system(player::add.system().after("worldgen"))
-            .add_startup_system(enemy::add.system().after("worldgen"))
+            // Generate the world before anything is placed in it
+            .init_resource::<levels::Depth>()
+            .init_resource::<worldgen::MapGenerators>()
+            .init_resource::<worldgen::connectivity::ConnectivityMode>()
+            .add_startup_system_to_stage(StartupStage::PreStartup, worldgen::generate.system())
+            // Add initial objects, in a fixed order so the entities and the random number
+            // generator's draws are the same on every run
+            .add_startup_system(player::add.system().label("spawn_player"))
+            .init_resource::<enemy::SpawnTable>()
+            .add_startup_system(
+                enemy::add
+                    .system()
+                    .label("spawn_enemies")
+                    .after("spawn_player"),
+            )
+            .init_resource::<items::ItemTable>()
+            .add_startup_system(items::add.system().after("spawn_enemies"))
+            // Combat
+            .add_event::<combat::AttackEvent>()
+            .add_event::<combat::DeathEvent>()
+            // Items
+            .add_event::<items::ItemEvent>()
+            // Saving and loading
+            .add_event::<save::SaveRequest>()
+            .add_event::<save::LoadRequest>()
+            .add_startup_system(save::request_load_on_startup.system())
+            // Loading happens before the update stage, so it doesn't clash with state transitions
+            .add_system_to_stage(CoreStage::PreUpdate, save::save_game.system())
+            .add_system_to_stage(CoreStage::PreUpdate, save::load_game.system())
+            // Going up and down the dungeon
+            .add_event::<levels::UseStairsEvent>()
+            .init_resource::<levels::LevelCache>()
             // Set the initial game state
-            .add_state(states::GameState::UpdateTilemap)
+            .init_resource::<states::TurnCount>()
+            .add_state(states::GameState::EndTurn)
+            // Keep track of which entities are on each tile
+            .init_resource::<occupancy::Occupancy>()
+            .add_system(occupancy::update_occupancy.system().label("occupancy"))
+            // Report what happened, before anything that was involved is despawned
+            .add_event::<movement::BlockedMoveEvent>()
+            .add_event::<messages::MessageEvent>()
+            .init_resource::<messages::MessageLog>()
+            .add_system(
+                messages::log_messages
+                    .system()
+                    .after("remove_dead")
+                    .after("change_level"),
+            )
+            // Keep combat stats in line with what entities have equipped
+            .add_system(equipment::update_combat_stats.system())
+            // Recompute what entities can see after they move
+            .add_system(fov::update_viewsheds.system())
+            // Recording and playing back replays
+            .add_system(replay::stop_recording_on_load.system())
+            // The app exits at the end of the frame, after the systems that asked it to
+            .add_system_to_stage(CoreStage::Last, replay::write_replay_on_exit.system())
             // When it's the player's turn
             .add_system_set(
                 SystemSet::on_update(states::GameState::PlayerTurn)
-                    // Accept player input
-                    .with_system(player::decide_action.system())
+                    // Play back the player's action from a replay
+                    .with_system(replay::play_back_action.system())
                     // End the player's turn
-                    .with_system(states::end_player_turn.system()),
+                    .with_system(states::end_player_turn.system().label("end_player_turn")),
+            )
+            // Record the player's decision
+            .add_system_set(
+                SystemSet::on_enter(states::GameState::EnemyTurn)
+                    .with_system(replay::record_player_action.system()),
             )
             // When it's the AI's turn
             .add_system_set(
                 SystemSet::on_update(states::GameState::EnemyTurn)
-                    // Choose the AI's action
-                    .with_system(enemy::choose_random_action.system())
+                    // Choose the AI's action, in a fixed order as they share the random number
+                    // generator
+                    .with_system(enemy::choose_random_action.system().label("wander"))
+                    .with_system(enemy::chase_player.system().after("wander"))
                     // End the enemy's turn
                     .with_system(states::end_enemy_turn.system()),
             )
             // When it's time to resolve the entities' chosen actions
             .add_system_set(
                 SystemSet::on_update(states::GameState::TakeAction)
+                    // Items are picked up and dropped before the actions are reset
+                    .with_system(
+                        items::pick_up_and_drop
+                            .system()
+                            .label("pick_up_and_drop")
+                            .after("occupancy"),
+                    )
+                    // Equipment is put on and taken off
+                    .with_system(
+                        equipment::equip_and_unequip
+                            .system()
+                            .label("equip_and_unequip")
+                            .after("pick_up_and_drop"),
+                    )
+                    // Items are used up before anyone moves
+                    .with_system(
+                        effects::use_items
+                            .system()
+                            .label("use_items")
+                            .after("equip_and_unequip"),
+                    )
                     // All the entities take their actions
-                    .with_system(movement::take_action.system())
+                    .with_system(
+                        movement::take_action
+                            .system()
+                            .label("take_action")
+                            .after("use_items"),
+                    )
+                    // Remove anything killed, before the tilemap is next updated
+                    .with_system(
+                        combat::remove_dead
+                            .system()
+                            .label("remove_dead")
+                            .after("take_action"),
+                    )
+                    // Move the player to another level once everyone has acted
+                    .with_system(
+                        levels::change_level
+                            .system()
+                            .label("change_level")
+                            .after("take_action"),
+                    )
                     // End taking actions
                     .with_system(states::end_action_state.system()),
             )
-            // When it's time to update the tilemap
+            // When the turn is over
             .add_system_set(
-                SystemSet::on_update(states::GameState::UpdateTilemap)
-                    // Update the tilemap
-                    .with_system(tilemap::update_tilemap.system())
-                    // Go back to the player's turn
-                    .with_system(states::end_update_tilemap_state.system()),
+                SystemSet::on_update(states::GameState::EndTurn)
+                    // Let time pass until someone can act
+                    .with_system(scheduler::advance_time.system().label("advance_time"))
+                    // Go to the turn of whoever can act next
+                    .with_system(states::end_turn.system().after("advance_time")),
             );
     }
 }
+
+/// Everything needed to play the game in a window: rendering and keyboard input.
+pub struct GraphicsPlugin;
+
+impl Plugin for GraphicsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app
+            // Use the default controls unless they have been configured
+            .init_resource::<settings::KeyBindings>()
+            // Create tilemap
+            .add_startup_system(setup::setup.system())
+            .add_system(helpers::texture::set_texture_filters_to_nearest.system())
+            // Add a camera
+            .add_startup_system(helpers::camera::add_camera.system())
+            .add_system(helpers::camera::movement.system())
+            // Show what the player is carrying
+            .init_resource::<inventory_screen::InventoryScreen>()
+            .add_startup_system(inventory_screen::setup.system())
+            .add_system(inventory_screen::update_inventory_screen.system())
+            // Show the message log
+            .init_resource::<message_panel::MessagePanel>()
+            .add_startup_system(message_panel::setup.system())
+            .add_system(
+                message_panel::scroll_messages
+                    .system()
+                    .label("scroll_messages"),
+            )
+            .add_system(
+                message_panel::update_message_panel
+                    .system()
+                    .after("scroll_messages"),
+            )
+            // Accept player input on the player's turn
+            .add_system_set(
+                SystemSet::on_update(states::GameState::PlayerTurn)
+                    .with_system(
+                        inventory_screen::browse_inventory
+                            .system()
+                            .label("browse_inventory"),
+                    )
+                    .with_system(player::decide_action.system().after("browse_inventory")),
+            )
+            .add_system(save::quicksave_and_quickload.system())
+            .add_system(replay::playback_controls.system())
+            // Mirror the world in the tilemap
+            .add_system(tilemap::update_walls.system())
+            .add_system(tilemap::update_tilemap.system().after("occupancy"))
+            .add_system(tilemap::update_targeting.system());
+    }
+}
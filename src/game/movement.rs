@@ -1,41 +1,382 @@
+use std::cmp::Reverse;
+
 use bevy::prelude::*;
-use bevy_ecs_tilemap::prelude::*;
+use rand::Rng;
+
+use crate::game::combat::{damage, AttackEvent};
+use crate::game::components::{
+    Action, BlocksTile, CombatStats, Energy, GoingToTakeAction, Health, Initiative, PlayerTag,
+    Position, Viewshed,
+};
+use crate::game::levels::UseStairsEvent;
+use crate::game::map::WorldMap;
+use crate::game::occupancy::Occupancy;
+use crate::game::rng::GameRng;
+use crate::game::scheduler::energy_cost;
 
-use crate::game::components::{Action, GoingToTakeAction, Position};
-use crate::game::config::{MAP_ID, WALLS_LAYER_ID};
-use crate::game::tilemap::is_in_bounds;
+/// An entity tried to walk into a wall.
+pub struct BlockedMoveEvent {
+    pub entity: Entity,
+}
+
+/// An entity's chosen action, waiting to be resolved.
+struct Intent {
+    entity: Entity,
+    from: Position,
+    delta: IVec2,
+    blocks_tile: bool,
+    is_player: bool,
+    initiative: i32,
+    // Breaks ties between entities with the same initiative
+    tie_breaker: u32,
+}
 
+/// Resolve every entity's chosen action.
+///
+/// Entities act in initiative order: the player first, then by their initiative, with ties broken
+/// at random. Attacks happen in that order. A move onto a tile that another entity is leaving
+/// waits for it to leave, so chains of entities can all move. Entities that are blocked, including
+/// entities trying to swap places, stay where they are.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 pub fn take_action(
-    mut moving_query: Query<(&mut Position, &mut GoingToTakeAction)>,
-    map_query: MapQuery,
+    mut moving_query: Query<(
+        Entity,
+        &mut Position,
+        &mut GoingToTakeAction,
+        Option<&mut Viewshed>,
+        Option<&BlocksTile>,
+        Option<&Initiative>,
+        Option<&mut Energy>,
+    )>,
+    players_query: Query<(), With<PlayerTag>>,
+    mut combatants_query: Query<(&mut Health, &CombatStats)>,
+    world_map: Res<WorldMap>,
+    mut occupancy: ResMut<Occupancy>,
+    mut rng: ResMut<GameRng>,
+    mut attack_events: EventWriter<AttackEvent>,
+    mut stairs_events: EventWriter<UseStairsEvent>,
+    mut blocked_move_events: EventWriter<BlockedMoveEvent>,
 ) {
-    for (mut entity_position, mut chosen_action) in moving_query.iter_mut() {
-        let from = *entity_position;
-        let delta = match &chosen_action.action {
-            Some(Action::North) => IVec2::new(0, 1),
-            Some(Action::South) => IVec2::new(0, -1),
-            Some(Action::West) => IVec2::new(-1, 0),
-            Some(Action::East) => IVec2::new(1, 0),
-            _ => IVec2::new(0, 0),
-        };
-        let to = IVec2::new(
-            (from.x as i32).wrapping_add(delta.x),
-            (from.y as i32).wrapping_add(delta.y),
-        );
-
-        let is_to_position_in_bounds = is_in_bounds(IVec2::new(to.x, to.y));
-        if is_to_position_in_bounds {
-            let will_collide_with_wall = map_query
-                .get_tile_entity(UVec2::new(to.x as u32, to.y as u32), MAP_ID, WALLS_LAYER_ID)
-                .is_ok();
-            if !will_collide_with_wall {
-                // Move the entity
-                entity_position.x = to.x as u32;
-                entity_position.y = to.y as u32;
+    let mut intents: Vec<Intent> = moving_query
+        .iter_mut()
+        .map(
+            |(entity, position, mut chosen_action, _, blocks_tile, initiative, energy)| {
+                let delta = action_delta(chosen_action.action);
+                // Changing level happens once everyone else has acted
+                if let Some(Action::UseStairs) = chosen_action.action {
+                    stairs_events.send(UseStairsEvent { entity });
+                }
+                // Pay for the action
+                if let (Some(action), Some(mut energy)) = (chosen_action.action, energy) {
+                    energy.0 -= energy_cost(action);
+                }
+                // Reset desired action
+                chosen_action.action = None;
+                Intent {
+                    entity,
+                    from: *position,
+                    delta,
+                    blocks_tile: blocks_tile.is_some(),
+                    is_player: players_query.get(entity).is_ok(),
+                    initiative: initiative.map_or(0, |initiative| initiative.0),
+                    tie_breaker: 0,
+                }
+            },
+        )
+        .collect();
+
+    // Put the entities in initiative order, drawing the tie breakers in a fixed order so the
+    // result only depends on the seed
+    intents.sort_by_key(|intent| intent.entity.id());
+    for intent in intents.iter_mut() {
+        intent.tie_breaker = rng.gen();
+    }
+    intents.sort_by_key(|intent| {
+        (
+            Reverse(intent.is_player),
+            Reverse(intent.initiative),
+            intent.tie_breaker,
+        )
+    });
+
+    // Only keep the actions that try to move onto another tile
+    let intents: Vec<(Intent, Position)> = intents
+        .into_iter()
+        .filter(|intent| intent.delta != IVec2::ZERO)
+        .filter_map(|intent| {
+            let to = IVec2::new(intent.from.x as i32, intent.from.y as i32) + intent.delta;
+            if world_map.is_walkable(to) {
+                let to = Position {
+                    x: to.x as u32,
+                    y: to.y as u32,
+                };
+                Some((intent, to))
+            } else {
+                blocked_move_events.send(BlockedMoveEvent {
+                    entity: intent.entity,
+                });
+                None
             }
+        })
+        .collect();
+
+    // Keep resolving actions until none of the remaining ones can be
+    let mut is_resolved = vec![false; intents.len()];
+    loop {
+        let mut has_resolved_any = false;
+        for i in 0..intents.len() {
+            if is_resolved[i] {
+                continue;
+            }
+            let (intent, to) = &intents[i];
+
+            // Dead entities can't act
+            let is_dead = combatants_query
+                .get_mut(intent.entity)
+                .map_or(false, |(health, _)| health.current <= 0);
+            if is_dead {
+                is_resolved[i] = true;
+                has_resolved_any = true;
+                continue;
+            }
+
+            if let Some(blocker) = occupancy.blocker_at(*to) {
+                // Moving into a hostile entity attacks it instead
+                let is_hostile = intent.is_player != players_query.get(blocker).is_ok();
+                if is_hostile {
+                    if let Some(damage) =
+                        attack_damage(&mut combatants_query, intent.entity, blocker)
+                    {
+                        attack_events.send(AttackEvent {
+                            attacker: intent.entity,
+                            target: blocker,
+                            damage,
+                        });
+                    }
+                    is_resolved[i] = true;
+                    has_resolved_any = true;
+                    continue;
+                }
+
+                // Wait for the blocker if it might still move out of the way
+                let is_blocker_waiting_to_move = intents
+                    .iter()
+                    .enumerate()
+                    .any(|(j, (other, _))| !is_resolved[j] && j != i && other.entity == blocker);
+                if !is_blocker_waiting_to_move {
+                    is_resolved[i] = true;
+                    has_resolved_any = true;
+                }
+                continue;
+            }
+
+            // Move the entity
+            let (_, mut entity_position, _, viewshed, _, _, _) =
+                moving_query.get_mut(intent.entity).unwrap();
+            *entity_position = *to;
+            occupancy.move_entity(intent.entity, intent.from, *to, intent.blocks_tile);
+
+            // What the entity can see has changed
+            if let Some(mut viewshed) = viewshed {
+                viewshed.is_dirty = true;
+            }
+
+            is_resolved[i] = true;
+            has_resolved_any = true;
+        }
+
+        // Anything left is stuck in a cycle, e.g. two entities swapping places
+        if !has_resolved_any {
+            break;
         }
+    }
+}
+
+/// Apply an attack, returning the damage dealt or `None` if either entity can't fight.
+fn attack_damage(
+    combatants_query: &mut Query<(&mut Health, &CombatStats)>,
+    attacker: Entity,
+    target: Entity,
+) -> Option<i32> {
+    let attacker_stats = combatants_query.get_mut(attacker).ok()?.1.clone();
+    let (mut target_health, target_stats) = combatants_query.get_mut(target).ok()?;
+    if target_health.current <= 0 {
+        return None;
+    }
+    let damage = damage(&attacker_stats, target_stats);
+    target_health.current -= damage;
+    Some(damage)
+}
+
+/// How far an action moves an entity.
+pub fn action_delta(action: Option<Action>) -> IVec2 {
+    match action {
+        Some(Action::North) => IVec2::new(0, 1),
+        Some(Action::South) => IVec2::new(0, -1),
+        Some(Action::West) => IVec2::new(-1, 0),
+        Some(Action::East) => IVec2::new(1, 0),
+        _ => IVec2::new(0, 0),
+    }
+}
+
+/// The action that moves an entity from one position to an adjacent one.
+pub fn action_towards(from: Position, to: Position) -> Action {
+    let delta = IVec2::new(to.x as i32 - from.x as i32, to.y as i32 - from.y as i32);
+    match (delta.x, delta.y) {
+        (0, 1) => Action::North,
+        (0, -1) => Action::South,
+        (-1, 0) => Action::West,
+        (1, 0) => Action::East,
+        _ => Action::StayStill,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::app::Events;
+
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::default();
+        world.insert_resource(WorldMap::new(10, 3));
+        world.insert_resource(Occupancy::new(10, 3));
+        world.insert_resource(GameRng::from_seed(0));
+        world.insert_resource(Events::<AttackEvent>::default());
+        world.insert_resource(Events::<UseStairsEvent>::default());
+        world.insert_resource(Events::<BlockedMoveEvent>::default());
+        world
+    }
+
+    /// Spawn an entity that blocks its tile and has chosen an action.
+    fn spawn(world: &mut World, x: u32, action: Action, initiative: i32) -> Entity {
+        let position = Position { x, y: 1 };
+        let entity = world
+            .spawn()
+            .insert_bundle((
+                position,
+                GoingToTakeAction {
+                    action: Some(action),
+                },
+                BlocksTile,
+                Initiative(initiative),
+                Health::new(10),
+                CombatStats {
+                    attack: 5,
+                    defence: 1,
+                },
+            ))
+            .id();
+        world
+            .get_resource_mut::<Occupancy>()
+            .unwrap()
+            .add(entity, position, true);
+        entity
+    }
+
+    fn run_take_action(world: &mut World) {
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(take_action.system());
+        stage.run(world);
+    }
+
+    fn x_of(world: &World, entity: Entity) -> u32 {
+        world.get::<Position>(entity).unwrap().x
+    }
+
+    /// The attacker, target and damage of every attack made.
+    fn attacks(world: &World) -> Vec<(Entity, Entity, i32)> {
+        let attack_events = world.get_resource::<Events<AttackEvent>>().unwrap();
+        attack_events
+            .get_reader()
+            .iter(attack_events)
+            .map(|attack| (attack.attacker, attack.target, attack.damage))
+            .collect()
+    }
+
+    #[test]
+    fn chains_of_entities_all_move() {
+        let mut world = world();
+        // The entity at the front of the chain acts last
+        let back = spawn(&mut world, 1, Action::East, 3);
+        let middle = spawn(&mut world, 2, Action::East, 2);
+        let front = spawn(&mut world, 3, Action::East, 1);
+
+        run_take_action(&mut world);
+
+        assert_eq!(x_of(&world, back), 2);
+        assert_eq!(x_of(&world, middle), 3);
+        assert_eq!(x_of(&world, front), 4);
+        let occupancy = world.get_resource::<Occupancy>().unwrap();
+        assert_eq!(occupancy.blocker_at(Position { x: 1, y: 1 }), None);
+        assert_eq!(occupancy.blocker_at(Position { x: 4, y: 1 }), Some(front));
+    }
+
+    #[test]
+    fn entities_swapping_places_stay_put() {
+        let mut world = world();
+        let left = spawn(&mut world, 1, Action::East, 0);
+        let right = spawn(&mut world, 2, Action::West, 0);
+
+        run_take_action(&mut world);
+
+        assert_eq!(x_of(&world, left), 1);
+        assert_eq!(x_of(&world, right), 2);
+    }
+
+    #[test]
+    fn higher_initiative_wins_a_contested_tile() {
+        let mut world = world();
+        let slow = spawn(&mut world, 1, Action::East, 1);
+        let quick = spawn(&mut world, 3, Action::West, 5);
+
+        run_take_action(&mut world);
+
+        assert_eq!(x_of(&world, slow), 1);
+        assert_eq!(x_of(&world, quick), 2);
+    }
+
+    #[test]
+    fn the_player_wins_a_contested_tile() {
+        let mut world = world();
+        let player = spawn(&mut world, 1, Action::East, 0);
+        world.entity_mut(player).insert(PlayerTag);
+        let enemy = spawn(&mut world, 3, Action::West, 5);
+
+        run_take_action(&mut world);
+
+        assert_eq!(x_of(&world, player), 2);
+        assert_eq!(x_of(&world, enemy), 3);
+        // The enemy attacks the player standing on the tile it wanted instead
+        assert_eq!(attacks(&world), vec![(enemy, player, 4)]);
+        assert_eq!(world.get::<Health>(player).unwrap().current, 6);
+    }
+
+    #[test]
+    fn moving_into_a_hostile_blocker_attacks_it() {
+        let mut world = world();
+        let player = spawn(&mut world, 1, Action::East, 0);
+        world.entity_mut(player).insert(PlayerTag);
+        let enemy = spawn(&mut world, 2, Action::StayStill, 0);
+
+        run_take_action(&mut world);
+
+        assert_eq!(x_of(&world, player), 1);
+        assert_eq!(world.get::<Health>(enemy).unwrap().current, 6);
+        assert_eq!(attacks(&world), vec![(player, enemy, 4)]);
+    }
+
+    #[test]
+    fn actions_are_reset_once_resolved() {
+        let mut world = world();
+        let entity = spawn(&mut world, 1, Action::East, 0);
+
+        run_take_action(&mut world);
 
-        // Reset desired action
-        chosen_action.action = None;
+        assert!(world
+            .get::<GoingToTakeAction>(entity)
+            .unwrap()
+            .action
+            .is_none());
     }
 }
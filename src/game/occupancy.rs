@@ -0,0 +1,84 @@
+use bevy::prelude::*;
+
+use crate::game::components::{BlocksTile, Position};
+use crate::game::map::WorldMap;
+
+/// A spatial index of which entities are on each tile.
+#[derive(Default)]
+pub struct Occupancy {
+    width: u32,
+    entities: Vec<Vec<Entity>>,
+    // At most one entity that blocks movement can be on each tile
+    blockers: Vec<Option<Entity>>,
+}
+
+impl Occupancy {
+    pub fn new(width: u32, height: u32) -> Self {
+        Occupancy {
+            width,
+            entities: vec![Vec::new(); (width * height) as usize],
+            blockers: vec![None; (width * height) as usize],
+        }
+    }
+
+    fn index(&self, position: Position) -> usize {
+        (position.y * self.width + position.x) as usize
+    }
+
+    /// Every entity on the tile.
+    pub fn entities_at(&self, position: Position) -> &[Entity] {
+        &self.entities[self.index(position)]
+    }
+
+    /// The entity blocking the tile, if there is one.
+    pub fn blocker_at(&self, position: Position) -> Option<Entity> {
+        self.blockers[self.index(position)]
+    }
+
+    pub fn is_blocked(&self, position: Position) -> bool {
+        self.blocker_at(position).is_some()
+    }
+
+    pub fn add(&mut self, entity: Entity, position: Position, blocks_tile: bool) {
+        let index = self.index(position);
+        self.entities[index].push(entity);
+        if blocks_tile {
+            self.blockers[index] = Some(entity);
+        }
+    }
+
+    pub fn remove(&mut self, entity: Entity, position: Position) {
+        let index = self.index(position);
+        self.entities[index].retain(|other| *other != entity);
+        if self.blockers[index] == Some(entity) {
+            self.blockers[index] = None;
+        }
+    }
+
+    pub fn move_entity(&mut self, entity: Entity, from: Position, to: Position, blocks_tile: bool) {
+        self.remove(entity, from);
+        self.add(entity, to, blocks_tile);
+    }
+}
+
+/// Rebuild the index when entities appear or disappear, or the map is replaced.
+///
+/// Movement keeps the index up to date itself, so this doesn't need to run every turn.
+pub fn update_occupancy(
+    entities_query: Query<(Entity, &Position, Option<&BlocksTile>)>,
+    new_entities_query: Query<(), Added<Position>>,
+    removed_positions: RemovedComponents<Position>,
+    world_map: Res<WorldMap>,
+    mut occupancy: ResMut<Occupancy>,
+) {
+    let have_entities_changed =
+        new_entities_query.iter().next().is_some() || removed_positions.iter().next().is_some();
+    if !world_map.is_changed() && !have_entities_changed {
+        return;
+    }
+
+    *occupancy = Occupancy::new(world_map.width, world_map.height);
+    for (entity, position, blocks_tile) in entities_query.iter() {
+        occupancy.add(entity, *position, blocks_tile.is_some());
+    }
+}
@@ -0,0 +1,155 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use bevy::prelude::*;
+
+use crate::game::components::Position;
+use crate::game::map::WorldMap;
+
+/// Find a shortest path between two positions with A*, moving in the four cardinal directions.
+///
+/// The path includes both `start` and `goal`, and ties are broken by position so the same path is
+/// always found.
+pub fn a_star(world_map: &WorldMap, start: Position, goal: Position) -> Option<Vec<Position>> {
+    let heuristic = |position: Position| manhattan_distance(position, goal);
+
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<Position, Position> = HashMap::new();
+    let mut cost_so_far: HashMap<Position, u32> = HashMap::new();
+    open.push(Reverse((heuristic(start), 0, start.x, start.y)));
+    cost_so_far.insert(start, 0);
+
+    while let Some(Reverse((_, cost, x, y))) = open.pop() {
+        let current = Position { x, y };
+        if current == goal {
+            return Some(reconstruct_path(&came_from, start, goal));
+        }
+        // Skip stale entries that have since been reached more cheaply
+        if cost > cost_so_far[&current] {
+            continue;
+        }
+
+        for delta in [
+            IVec2::new(0, 1),
+            IVec2::new(0, -1),
+            IVec2::new(-1, 0),
+            IVec2::new(1, 0),
+        ] {
+            let next = IVec2::new(current.x as i32, current.y as i32) + delta;
+            if !world_map.is_walkable(next) {
+                continue;
+            }
+            let next = Position {
+                x: next.x as u32,
+                y: next.y as u32,
+            };
+            let next_cost = cost + 1;
+            let is_better = cost_so_far
+                .get(&next)
+                .map_or(true, |&previous_cost| next_cost < previous_cost);
+            if is_better {
+                cost_so_far.insert(next, next_cost);
+                came_from.insert(next, current);
+                open.push(Reverse((
+                    next_cost + heuristic(next),
+                    next_cost,
+                    next.x,
+                    next.y,
+                )));
+            }
+        }
+    }
+
+    None
+}
+
+pub fn manhattan_distance(a: Position, b: Position) -> u32 {
+    ((a.x as i32 - b.x as i32).abs() + (a.y as i32 - b.y as i32).abs()) as u32
+}
+
+fn reconstruct_path(
+    came_from: &HashMap<Position, Position>,
+    start: Position,
+    goal: Position,
+) -> Vec<Position> {
+    let mut path = vec![goal];
+    let mut current = goal;
+    while current != start {
+        current = came_from[&current];
+        path.push(current);
+    }
+    path.reverse();
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check every step of the path moves to an adjacent floor tile.
+    fn assert_walkable(world_map: &WorldMap, path: &[Position]) {
+        for step in path.windows(2) {
+            assert_eq!(manhattan_distance(step[0], step[1]), 1);
+            assert!(world_map.is_walkable(IVec2::new(step[1].x as i32, step[1].y as i32)));
+        }
+    }
+
+    #[test]
+    fn finds_a_shortest_path_around_walls() {
+        let world_map = WorldMap::from_rows(&[
+            ".......", //
+            ".#####.", ".....#.", "####.#.", ".......",
+        ]);
+        let start = Position { x: 0, y: 2 };
+        let goal = Position { x: 0, y: 4 };
+
+        let path = a_star(&world_map, start, goal).unwrap();
+
+        assert_eq!(path.first(), Some(&start));
+        assert_eq!(path.last(), Some(&goal));
+        assert_walkable(&world_map, &path);
+        // Through the gap in the middle, rather than the long way round the outside
+        assert_eq!(path.len(), 11);
+    }
+
+    #[test]
+    fn path_to_the_start_is_just_the_start() {
+        let world_map = WorldMap::new(3, 3);
+        let start = Position { x: 1, y: 1 };
+
+        assert_eq!(a_star(&world_map, start, start), Some(vec![start]));
+    }
+
+    #[test]
+    fn unreachable_goals_have_no_path() {
+        let world_map = WorldMap::from_rows(&[
+            "..#..", //
+            "..#..", "..#..",
+        ]);
+
+        assert_eq!(
+            a_star(&world_map, Position { x: 0, y: 1 }, Position { x: 4, y: 1 }),
+            None
+        );
+        // Nor do goals inside walls
+        assert_eq!(
+            a_star(&world_map, Position { x: 0, y: 1 }, Position { x: 2, y: 1 }),
+            None
+        );
+    }
+
+    #[test]
+    fn the_same_path_is_always_found() {
+        let world_map = WorldMap::new(8, 8);
+        let start = Position { x: 0, y: 0 };
+        let goal = Position { x: 7, y: 7 };
+
+        let path = a_star(&world_map, start, goal).unwrap();
+
+        assert_eq!(path.len(), 15);
+        assert_walkable(&world_map, &path);
+        for _ in 0..10 {
+            assert_eq!(a_star(&world_map, start, goal).unwrap(), path);
+        }
+    }
+}
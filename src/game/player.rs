@@ -1,36 +1,112 @@
 use bevy::prelude::*;
 
-use crate::game::components::{Action, Drawable, GoingToTakeAction, PlayerTag, Position};
-use crate::game::config::PLAYER_TEXTURE_INDEX;
+use crate::game::components::{
+    Action, BaseCombatStats, BlocksTile, CombatStats, DisplayName, Drawable, Energy, Equipment,
+    GoingToTakeAction, Health, Initiative, Inventory, Item, MapMemory, PlayerTag, Position, Speed,
+    Viewshed,
+};
+use crate::game::config::{
+    PLAYER_ATTACK, PLAYER_DEFENCE, PLAYER_INITIATIVE, PLAYER_INVENTORY_CAPACITY, PLAYER_MAX_HEALTH,
+    PLAYER_SIGHT_RANGE, PLAYER_SPEED, PLAYER_TEXTURE_INDEX,
+};
+use crate::game::inventory_screen::InventoryScreen;
+use crate::game::items::is_item_at;
+use crate::game::map::{MapLayout, TileKind, WorldMap};
+use crate::game::occupancy::Occupancy;
+use crate::game::replay::ReplayPlayback;
+use crate::game::settings::KeyBindings;
 
-pub fn add(mut commands: Commands) {
-    commands.spawn_bundle((
-        PlayerTag,
-        Position { x: 10, y: 10 },
-        Drawable {
-            texture_index: PLAYER_TEXTURE_INDEX,
-        },
-        GoingToTakeAction { action: None },
-    ));
+pub fn add(mut commands: Commands, world_map: Res<WorldMap>, layout: Res<MapLayout>) {
+    // World generation always puts the best floor tile to start on first
+    let position = *layout
+        .spawn_points
+        .first()
+        .expect("The map should have a spawn point.");
+
+    // Bundles can hold at most 15 components, so the inventory and equipment are inserted after
+    commands
+        .spawn_bundle((
+            PlayerTag,
+            DisplayName("player".to_string()),
+            position,
+            Drawable {
+                texture_index: PLAYER_TEXTURE_INDEX,
+            },
+            GoingToTakeAction { action: None },
+            BlocksTile,
+            Initiative(PLAYER_INITIATIVE),
+            Energy::default(),
+            Speed(PLAYER_SPEED),
+            Viewshed::new(PLAYER_SIGHT_RANGE),
+            MapMemory::new(world_map.width, world_map.height),
+            Health::new(PLAYER_MAX_HEALTH),
+            BaseCombatStats {
+                attack: PLAYER_ATTACK,
+                defence: PLAYER_DEFENCE,
+            },
+            CombatStats {
+                attack: PLAYER_ATTACK,
+                defence: PLAYER_DEFENCE,
+            },
+        ))
+        .insert_bundle((
+            Inventory::new(PLAYER_INVENTORY_CAPACITY),
+            Equipment::default(),
+        ));
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn decide_action(
     keys: Res<Input<KeyCode>>,
-    mut player_query: Query<&mut GoingToTakeAction, With<PlayerTag>>,
+    key_bindings: Res<KeyBindings>,
+    mut player_query: Query<(&Position, &mut GoingToTakeAction), With<PlayerTag>>,
+    floor_items_query: Query<(), With<Item>>,
+    world_map: Res<WorldMap>,
+    occupancy: Res<Occupancy>,
+    inventory_screen: Res<InventoryScreen>,
+    playback: Option<Res<ReplayPlayback>>,
 ) {
+    // The replay chooses the player's actions instead, and the keys browse the inventory while it
+    // is open
+    if playback.is_some() || inventory_screen.is_active() {
+        return;
+    }
+
     for key in keys.get_just_released() {
-        let chosen_action = match key {
-            KeyCode::Left => Some(Action::West),
-            KeyCode::Right => Some(Action::East),
-            KeyCode::Up => Some(Action::North),
-            KeyCode::Down => Some(Action::South),
-            _ => None,
+        let chosen_action = if *key == key_bindings.west {
+            Some(Action::West)
+        } else if *key == key_bindings.east {
+            Some(Action::East)
+        } else if *key == key_bindings.north {
+            Some(Action::North)
+        } else if *key == key_bindings.south {
+            Some(Action::South)
+        } else if *key == key_bindings.use_stairs {
+            Some(Action::UseStairs)
+        } else if *key == key_bindings.pick_up {
+            Some(Action::PickUp)
+        } else {
+            None
         };
 
         if chosen_action.is_some() {
-            let mut player_action = player_query
+            let (position, mut player_action) = player_query
                 .single_mut()
                 .expect("There should be exactly one player in the game.");
+            // Don't waste a turn looking for stairs that aren't there
+            let is_on_stairs = matches!(
+                world_map.get(position.x, position.y),
+                TileKind::DownStairs | TileKind::UpStairs
+            );
+            if let (Some(Action::UseStairs), false) = (chosen_action, is_on_stairs) {
+                continue;
+            }
+            // Or picking up nothing
+            if let Some(Action::PickUp) = chosen_action {
+                if !is_item_at(&occupancy, &floor_items_query, *position) {
+                    continue;
+                }
+            }
             player_action.action = chosen_action;
         }
     }
@@ -0,0 +1,261 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::{Action, GoingToTakeAction, PlayerTag};
+use crate::game::enemy::SpawnSettings;
+use crate::game::items::ItemSpawnSettings;
+use crate::game::map::MapDimensions;
+use crate::game::save::LoadRequest;
+use crate::game::settings::{KeyBindings, WorldgenConfig};
+use crate::game::worldgen::SelectedGenerator;
+
+// File the current run is recorded to
+pub const REPLAY_PATH: &str = "last_run.replay.ron";
+// The replay is written out after this many actions, so little is lost if the game crashes
+const ACTIONS_PER_WRITE: usize = 100;
+
+/// Everything needed to reproduce a run: the seed, the settings that shape the game and the
+/// player's action on every turn.
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    pub version: String,
+    pub seed: u64,
+    pub settings: ReplaySettings,
+    pub actions: Vec<Action>,
+}
+
+/// The settings a run was played with, as the same seed makes a different game without them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplaySettings {
+    pub map_dimensions: MapDimensions,
+    pub generator: String,
+    pub worldgen: WorldgenConfig,
+    pub enemies: SpawnSettings,
+    pub items: ItemSpawnSettings,
+}
+
+impl ReplaySettings {
+    /// The settings the game in this world is being played with.
+    pub fn from_world(world: &World) -> Self {
+        ReplaySettings {
+            map_dimensions: *world.get_resource::<MapDimensions>().unwrap(),
+            generator: world.get_resource::<SelectedGenerator>().unwrap().0.clone(),
+            worldgen: world.get_resource::<WorldgenConfig>().unwrap().clone(),
+            enemies: world.get_resource::<SpawnSettings>().unwrap().clone(),
+            items: world.get_resource::<ItemSpawnSettings>().unwrap().clone(),
+        }
+    }
+}
+
+impl Replay {
+    pub fn new(seed: u64, settings: ReplaySettings) -> Self {
+        Replay {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            seed,
+            settings,
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let replay: Replay = ron::de::from_str(&contents).map_err(|error| error.to_string())?;
+        if replay.version != env!("CARGO_PKG_VERSION") {
+            log::warn!(
+                "{} was recorded by version {} of the game.",
+                path.display(),
+                replay.version
+            );
+        }
+        Ok(replay)
+    }
+
+    /// Check the game is set up the way it was when the run was recorded, naming the first
+    /// setting that isn't.
+    pub fn check_settings(&self, settings: &ReplaySettings) -> Result<(), String> {
+        let recorded = &self.settings;
+        if recorded.map_dimensions != settings.map_dimensions {
+            return Err(format!(
+                "The replay was recorded on a {}x{} map, not {}x{}.",
+                recorded.map_dimensions.width,
+                recorded.map_dimensions.height,
+                settings.map_dimensions.width,
+                settings.map_dimensions.height
+            ));
+        }
+        if recorded.generator != settings.generator {
+            return Err(format!(
+                "The replay was recorded with the {:?} generator, not {:?}.",
+                recorded.generator, settings.generator
+            ));
+        }
+
+        let check = |is_same: bool, section: &str| {
+            if is_same {
+                Ok(())
+            } else {
+                Err(format!(
+                    "The replay was recorded with different {} settings.",
+                    section
+                ))
+            }
+        };
+        check(recorded.worldgen == settings.worldgen, "worldgen")?;
+        check(recorded.enemies == settings.enemies, "enemies")?;
+        check(recorded.items == settings.items, "items")
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .map_err(|error| error.to_string())?;
+        fs::write(path, contents).map_err(|error| error.to_string())
+    }
+}
+
+/// Records the player's actions as the game is played.
+pub struct ReplayRecorder {
+    pub path: PathBuf,
+    pub replay: Replay,
+    // Recording stops when a saved game is loaded, as the replay can no longer reproduce the run
+    pub is_recording: bool,
+}
+
+impl ReplayRecorder {
+    /// Write the actions recorded so far to the replay file.
+    fn write(&self) {
+        if let Err(error) = self.replay.write(&self.path) {
+            log::error!(
+                "Couldn't write the replay to {}: {}",
+                self.path.display(),
+                error
+            );
+        }
+    }
+}
+
+/// Feeds the actions from a replay to the player instead of the keyboard.
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    pub next_action: usize,
+    pub is_paused: bool,
+    // Take a single turn while paused
+    pub is_stepping: bool,
+    // Every action has been played back
+    pub is_finished: bool,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        ReplayPlayback {
+            replay,
+            next_action: 0,
+            is_paused: false,
+            is_stepping: false,
+            is_finished: false,
+        }
+    }
+}
+
+pub fn record_player_action(
+    player_query: Query<&GoingToTakeAction, With<PlayerTag>>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+) {
+    let mut recorder = match recorder {
+        Some(recorder) if recorder.is_recording => recorder,
+        _ => return,
+    };
+
+    for player_action in player_query.iter() {
+        if let Some(action) = player_action.action {
+            recorder.replay.actions.push(action);
+            // Rewriting the whole replay every turn would slow down long runs
+            if recorder.replay.actions.len() % ACTIONS_PER_WRITE == 0 {
+                recorder.write();
+            }
+        }
+    }
+}
+
+/// Write out the rest of the replay when the game exits.
+pub fn write_replay_on_exit(
+    mut app_exit_events: EventReader<AppExit>,
+    recorder: Option<Res<ReplayRecorder>>,
+) {
+    if app_exit_events.iter().next().is_none() {
+        return;
+    }
+    if let Some(recorder) = recorder {
+        if recorder.is_recording {
+            recorder.write();
+        }
+    }
+}
+
+pub fn stop_recording_on_load(
+    mut load_requests: EventReader<LoadRequest>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+) {
+    if let Some(mut recorder) = recorder {
+        if load_requests.iter().next().is_some() && recorder.is_recording {
+            log::warn!("A saved game was loaded, so the replay is no longer being recorded.");
+            // Keep the replay of the run up to the load
+            recorder.write();
+            recorder.is_recording = false;
+        }
+    }
+}
+
+pub fn play_back_action(
+    mut player_query: Query<&mut GoingToTakeAction, With<PlayerTag>>,
+    playback: Option<ResMut<ReplayPlayback>>,
+) {
+    let mut playback = match playback {
+        Some(playback) => playback,
+        None => return,
+    };
+    if playback.is_paused && !playback.is_stepping {
+        return;
+    }
+
+    for mut player_action in player_query.iter_mut() {
+        // Wait until the previous action has been resolved
+        if player_action.action.is_some() {
+            continue;
+        }
+        match playback.replay.actions.get(playback.next_action).copied() {
+            Some(action) => {
+                player_action.action = Some(action);
+                playback.next_action += 1;
+                playback.is_stepping = false;
+            }
+            None => {
+                if !playback.is_finished {
+                    log::info!("Finished playing back the replay.");
+                }
+                playback.is_paused = true;
+                playback.is_stepping = false;
+                playback.is_finished = true;
+            }
+        }
+    }
+}
+
+pub fn playback_controls(
+    keys: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    playback: Option<ResMut<ReplayPlayback>>,
+) {
+    if let Some(mut playback) = playback {
+        if keys.just_released(key_bindings.pause_replay) {
+            playback.is_paused = !playback.is_paused;
+        }
+        if keys.just_released(key_bindings.step_replay) {
+            playback.is_paused = true;
+            playback.is_stepping = true;
+        }
+    }
+}
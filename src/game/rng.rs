@@ -0,0 +1,58 @@
+use rand::{Error, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+// Environment variable that can be used to fix the seed of a run
+pub const SEED_ENV_VAR: &str = "DINK_SEED";
+
+/// The single source of randomness for the game.
+///
+/// Worldgen, spawning and AI all draw from this resource, so the same seed and the
+/// same player inputs always produce the same game.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GameRng {
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl GameRng {
+    pub fn from_seed(seed: u64) -> Self {
+        GameRng {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Use the seed from the environment if one is set, otherwise pick one at random.
+    pub fn from_env_or_random() -> Self {
+        let seed = match std::env::var(SEED_ENV_VAR) {
+            Ok(value) => value
+                .parse()
+                .unwrap_or_else(|_| panic!("{} must be an unsigned integer.", SEED_ENV_VAR)),
+            Err(_) => rand::random(),
+        };
+        Self::from_seed(seed)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
@@ -0,0 +1,352 @@
+use std::fs;
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::{
+    BaseCombatStats, BlocksTile, ChasePlayerTag, CombatStats, DisplayName, Drawable, EnemyTag,
+    Energy, Equipment, GoingToTakeAction, Health, Initiative, Inventory, Item, MapMemory,
+    MoveRandomlyTag, PlayerTag, Position, Speed, Viewshed,
+};
+use crate::game::levels::{Depth, LevelCache};
+use crate::game::map::{MapDimensions, WorldMap};
+use crate::game::rng::GameRng;
+use crate::game::settings::KeyBindings;
+use crate::game::states::{GameState, TurnCount};
+
+// File used by the quicksave and quickload hotkeys
+pub const QUICKSAVE_PATH: &str = "quicksave.ron";
+
+/// Ask for the game to be saved to a file.
+pub struct SaveRequest(pub PathBuf);
+
+/// Ask for the game to be replaced by the one saved in a file.
+pub struct LoadRequest(pub PathBuf);
+
+/// A file to load as soon as the game has started.
+pub struct LoadOnStartup(pub PathBuf);
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: String,
+    pub game_state: GameState,
+    pub turn_count: u32,
+    pub rng: GameRng,
+    pub world_map: WorldMap,
+    pub entities: Vec<SavedEntity>,
+    #[serde(default)]
+    pub depth: Depth,
+    #[serde(default)]
+    pub level_cache: LevelCache,
+}
+
+/// The components of a single entity, any of which may be missing.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SavedEntity {
+    #[serde(default)]
+    pub position: Option<Position>,
+    #[serde(default)]
+    pub drawable: Option<Drawable>,
+    #[serde(default)]
+    pub going_to_take_action: Option<GoingToTakeAction>,
+    #[serde(default)]
+    pub display_name: Option<DisplayName>,
+    #[serde(default)]
+    pub viewshed: Option<Viewshed>,
+    #[serde(default)]
+    pub map_memory: Option<MapMemory>,
+    #[serde(default)]
+    pub health: Option<Health>,
+    #[serde(default)]
+    pub combat_stats: Option<CombatStats>,
+    #[serde(default)]
+    pub base_combat_stats: Option<BaseCombatStats>,
+    #[serde(default)]
+    pub initiative: Option<Initiative>,
+    #[serde(default)]
+    pub energy: Option<Energy>,
+    #[serde(default)]
+    pub speed: Option<Speed>,
+    #[serde(default)]
+    pub item: Option<Item>,
+    #[serde(default)]
+    pub inventory: Option<Inventory>,
+    #[serde(default)]
+    pub equipment: Option<Equipment>,
+    #[serde(default)]
+    pub blocks_tile: bool,
+    #[serde(default)]
+    pub player: bool,
+    #[serde(default)]
+    pub enemy: bool,
+    #[serde(default)]
+    pub move_randomly: bool,
+    #[serde(default)]
+    pub chase_player: bool,
+}
+
+/// The components that are saved, as read from an entity by a query.
+pub type SavableComponents<'a> = (
+    (
+        Option<&'a Position>,
+        Option<&'a Drawable>,
+        Option<&'a GoingToTakeAction>,
+        Option<&'a DisplayName>,
+    ),
+    (Option<&'a Viewshed>, Option<&'a MapMemory>),
+    (
+        Option<&'a Health>,
+        Option<&'a CombatStats>,
+        Option<&'a BaseCombatStats>,
+    ),
+    (
+        Option<&'a Initiative>,
+        Option<&'a Energy>,
+        Option<&'a Speed>,
+    ),
+    (
+        Option<&'a Item>,
+        Option<&'a Inventory>,
+        Option<&'a Equipment>,
+    ),
+    (
+        Option<&'a BlocksTile>,
+        Option<&'a PlayerTag>,
+        Option<&'a EnemyTag>,
+        Option<&'a MoveRandomlyTag>,
+        Option<&'a ChasePlayerTag>,
+    ),
+);
+
+impl SavedEntity {
+    pub fn from_components(
+        (
+            (position, drawable, going_to_take_action, display_name),
+            (viewshed, map_memory),
+            (health, combat_stats, base_combat_stats),
+            (initiative, energy, speed),
+            (item, inventory, equipment),
+            (blocks_tile, player, enemy, move_randomly, chase_player),
+        ): SavableComponents,
+    ) -> Self {
+        SavedEntity {
+            position: position.copied(),
+            drawable: drawable.cloned(),
+            going_to_take_action: going_to_take_action.cloned(),
+            display_name: display_name.cloned(),
+            viewshed: viewshed.cloned(),
+            map_memory: map_memory.cloned(),
+            health: health.cloned(),
+            combat_stats: combat_stats.cloned(),
+            base_combat_stats: base_combat_stats.cloned(),
+            initiative: initiative.copied(),
+            energy: energy.copied(),
+            speed: speed.copied(),
+            item: item.cloned(),
+            inventory: inventory.cloned(),
+            equipment: equipment.cloned(),
+            blocks_tile: blocks_tile.is_some(),
+            player: player.is_some(),
+            enemy: enemy.is_some(),
+            move_randomly: move_randomly.is_some(),
+            chase_player: chase_player.is_some(),
+        }
+    }
+
+    /// Spawn an entity with the saved components.
+    pub fn spawn(self, commands: &mut Commands) -> Entity {
+        let mut entity_commands = commands.spawn();
+        if let Some(position) = self.position {
+            entity_commands.insert(position);
+        }
+        if let Some(drawable) = self.drawable {
+            entity_commands.insert(drawable);
+        }
+        if let Some(going_to_take_action) = self.going_to_take_action {
+            entity_commands.insert(going_to_take_action);
+        }
+        if let Some(display_name) = self.display_name {
+            entity_commands.insert(display_name);
+        }
+        if let Some(viewshed) = self.viewshed {
+            entity_commands.insert(viewshed);
+        }
+        if let Some(map_memory) = self.map_memory {
+            entity_commands.insert(map_memory);
+        }
+        if let Some(health) = self.health {
+            entity_commands.insert(health);
+        }
+        if let Some(combat_stats) = self.combat_stats {
+            entity_commands.insert(combat_stats);
+        }
+        if let Some(base_combat_stats) = self.base_combat_stats {
+            entity_commands.insert(base_combat_stats);
+        }
+        if let Some(initiative) = self.initiative {
+            entity_commands.insert(initiative);
+        }
+        if let Some(energy) = self.energy {
+            entity_commands.insert(energy);
+        }
+        if let Some(speed) = self.speed {
+            entity_commands.insert(speed);
+        }
+        if let Some(item) = self.item {
+            entity_commands.insert(item);
+        }
+        if let Some(inventory) = self.inventory {
+            entity_commands.insert(inventory);
+        }
+        if let Some(equipment) = self.equipment {
+            entity_commands.insert(equipment);
+        }
+        if self.blocks_tile {
+            entity_commands.insert(BlocksTile);
+        }
+        if self.player {
+            entity_commands.insert(PlayerTag);
+        }
+        if self.enemy {
+            entity_commands.insert(EnemyTag);
+        }
+        if self.move_randomly {
+            entity_commands.insert(MoveRandomlyTag);
+        }
+        if self.chase_player {
+            entity_commands.insert(ChasePlayerTag);
+        }
+        entity_commands.id()
+    }
+}
+
+pub fn request_load_on_startup(
+    load_on_startup: Option<Res<LoadOnStartup>>,
+    mut load_requests: EventWriter<LoadRequest>,
+) {
+    if let Some(load_on_startup) = load_on_startup {
+        load_requests.send(LoadRequest(load_on_startup.0.clone()));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn save_game(
+    mut save_requests: EventReader<SaveRequest>,
+    entities_query: Query<SavableComponents, With<Position>>,
+    game_state: Res<State<GameState>>,
+    turn_count: Res<TurnCount>,
+    rng: Res<GameRng>,
+    world_map: Res<WorldMap>,
+    depth: Res<Depth>,
+    level_cache: Res<LevelCache>,
+) {
+    for SaveRequest(path) in save_requests.iter() {
+        let entities = entities_query
+            .iter()
+            .map(SavedEntity::from_components)
+            .collect();
+        let save_game = SaveGame {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            game_state: game_state.current().clone(),
+            turn_count: turn_count.0,
+            rng: rng.clone(),
+            world_map: world_map.clone(),
+            entities,
+            depth: *depth,
+            level_cache: level_cache.clone(),
+        };
+
+        let result = ron::ser::to_string_pretty(&save_game, ron::ser::PrettyConfig::new())
+            .map_err(|error| error.to_string())
+            .and_then(|contents| fs::write(path, contents).map_err(|error| error.to_string()));
+        match result {
+            Ok(()) => log::info!("Saved the game to {}.", path.display()),
+            Err(error) => log::error!("Couldn't save the game to {}: {}", path.display(), error),
+        }
+    }
+}
+
+pub fn load_game(
+    mut commands: Commands,
+    mut load_requests: EventReader<LoadRequest>,
+    entities_query: Query<Entity, With<Position>>,
+    mut game_state: ResMut<State<GameState>>,
+    dimensions: Res<MapDimensions>,
+) {
+    // Only the most recent request matters, as each load replaces the whole game
+    let path = match load_requests.iter().last() {
+        Some(LoadRequest(path)) => path,
+        None => return,
+    };
+
+    let save_game = match fs::read_to_string(path)
+        .map_err(|error| error.to_string())
+        .and_then(|contents| {
+            ron::de::from_str::<SaveGame>(&contents).map_err(|error| error.to_string())
+        }) {
+        Ok(save_game) => save_game,
+        Err(error) => {
+            log::error!("Couldn't load the game from {}: {}", path.display(), error);
+            return;
+        }
+    };
+    if save_game.version != env!("CARGO_PKG_VERSION") {
+        log::warn!(
+            "{} was saved by version {} of the game.",
+            path.display(),
+            save_game.version
+        );
+    }
+
+    // The tilemap is built once at startup, so it can't show a map of another size
+    if (save_game.world_map.width, save_game.world_map.height)
+        != (dimensions.width, dimensions.height)
+    {
+        log::error!(
+            "Couldn't load the game from {}: its map is {}x{} but this game's map is {}x{}.",
+            path.display(),
+            save_game.world_map.width,
+            save_game.world_map.height,
+            dimensions.width,
+            dimensions.height
+        );
+        return;
+    }
+
+    // Remove the current game's entities
+    for entity in entities_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    // Replace them with the saved entities
+    for saved_entity in save_game.entities {
+        saved_entity.spawn(&mut commands);
+    }
+
+    // The tilemap is rebuilt from the world map once it has been replaced
+    commands.insert_resource(save_game.world_map);
+    commands.insert_resource(save_game.rng);
+    commands.insert_resource(TurnCount(save_game.turn_count));
+    commands.insert_resource(save_game.depth);
+    commands.insert_resource(save_game.level_cache);
+    if game_state.current() != &save_game.game_state {
+        game_state.overwrite_replace(save_game.game_state).unwrap();
+    }
+
+    log::info!("Loaded the game from {}.", path.display());
+}
+
+pub fn quicksave_and_quickload(
+    keys: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut save_requests: EventWriter<SaveRequest>,
+    mut load_requests: EventWriter<LoadRequest>,
+) {
+    if keys.just_released(key_bindings.quicksave) {
+        save_requests.send(SaveRequest(PathBuf::from(QUICKSAVE_PATH)));
+    }
+    if keys.just_released(key_bindings.quickload) {
+        load_requests.send(LoadRequest(PathBuf::from(QUICKSAVE_PATH)));
+    }
+}
@@ -0,0 +1,47 @@
+use bevy::prelude::*;
+
+use crate::game::components::{Action, Energy, Speed};
+use crate::game::config::{
+    ACTION_ENERGY_THRESHOLD, DROP_ENERGY_COST, EQUIP_ENERGY_COST, MOVE_ENERGY_COST,
+    PICK_UP_ENERGY_COST, STAY_STILL_ENERGY_COST, USE_ITEM_ENERGY_COST, USE_STAIRS_ENERGY_COST,
+};
+
+/// Whether an entity has enough energy to act.
+pub fn is_ready(energy: &Energy) -> bool {
+    energy.0 >= ACTION_ENERGY_THRESHOLD
+}
+
+/// The energy an entity spends taking an action.
+pub fn energy_cost(action: Action) -> i32 {
+    match action {
+        Action::StayStill => STAY_STILL_ENERGY_COST,
+        Action::North | Action::South | Action::West | Action::East => MOVE_ENERGY_COST,
+        Action::UseStairs => USE_STAIRS_ENERGY_COST,
+        Action::PickUp => PICK_UP_ENERGY_COST,
+        Action::Drop(_) => DROP_ENERGY_COST,
+        Action::UseItem { .. } => USE_ITEM_ENERGY_COST,
+        Action::Equip(_) | Action::Unequip(_) => EQUIP_ENERGY_COST,
+    }
+}
+
+/// Advance time until at least one entity has enough energy to act.
+///
+/// Every tick each entity gains energy equal to its speed, so fast entities act more often than
+/// slow ones.
+pub fn advance_time(mut actors_query: Query<(&mut Energy, &Speed)>) {
+    // Find how many ticks it takes for the first entity to be ready
+    let ticks = actors_query
+        .iter_mut()
+        .filter(|(_, speed)| speed.0 > 0)
+        .map(|(energy, speed)| {
+            let energy_needed = (ACTION_ENERGY_THRESHOLD - energy.0).max(0);
+            (energy_needed + speed.0 - 1) / speed.0
+        })
+        .min();
+
+    if let Some(ticks) = ticks {
+        for (mut energy, speed) in actors_query.iter_mut() {
+            energy.0 += ticks * speed.0;
+        }
+    }
+}
@@ -0,0 +1,396 @@
+use std::fs;
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::config::{
+    BSP_MIN_LEAF_SIZE, BSP_MIN_ROOM_SIZE, CAVE_BIRTH_LIMIT, CAVE_FILL_PROBABILITY,
+    CAVE_SMOOTHING_PASSES, CAVE_SURVIVAL_LIMIT, DEFAULT_GENERATOR, DEFAULT_MAP_HEIGHT,
+    DEFAULT_MAP_WIDTH, ENEMY_COUNT, ENEMY_MAX_DENSITY, ENEMY_MIN_DISTANCE_FROM_PLAYER,
+    ENEMY_SPAWN_ATTEMPTS, ITEM_COUNT, ITEM_SPAWN_ATTEMPTS, NOISE_SCALE, NOISE_WALL_THRESHOLD,
+};
+use crate::game::enemy::SpawnSettings;
+use crate::game::items::ItemSpawnSettings;
+use crate::game::map::{MapDimensions, MAP_SIZE_ENV_VAR};
+use crate::game::worldgen::caves::CaveGenerator;
+use crate::game::worldgen::connectivity::ConnectivityMode;
+use crate::game::worldgen::noise::NoiseGenerator;
+use crate::game::worldgen::rooms::RoomsGenerator;
+use crate::game::worldgen::{MapGenerators, SelectedGenerator, GENERATOR_ENV_VAR};
+
+// The configuration file that is read if it exists and no other file is given
+pub const CONFIG_PATH: &str = "config.ron";
+
+/// Everything that can be tuned without recompiling the game.
+///
+/// Every field is optional in the file, missing ones take their default value.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    pub window: WindowConfig,
+    pub map: MapConfig,
+    pub worldgen: WorldgenConfig,
+    pub enemies: EnemiesConfig,
+    pub items: ItemsConfig,
+    pub key_bindings: KeyBindings,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub width: f32,
+    pub height: f32,
+    pub title: String,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            width: (1920 / 2) as f32,
+            height: (1080 / 2) as f32,
+            title: String::from("Roguelike"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MapConfig {
+    pub width: u32,
+    pub height: u32,
+    pub generator: String,
+}
+
+impl Default for MapConfig {
+    fn default() -> Self {
+        MapConfig {
+            width: DEFAULT_MAP_WIDTH,
+            height: DEFAULT_MAP_HEIGHT,
+            generator: DEFAULT_GENERATOR.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldgenConfig {
+    pub noise_scale: f64,
+    pub noise_wall_threshold: f64,
+    pub cave_fill_probability: f64,
+    pub cave_smoothing_passes: u32,
+    pub cave_birth_limit: u32,
+    pub cave_survival_limit: u32,
+    pub rooms_min_leaf_size: u32,
+    pub rooms_min_room_size: u32,
+    pub connectivity: ConnectivityMode,
+}
+
+impl Default for WorldgenConfig {
+    fn default() -> Self {
+        WorldgenConfig {
+            noise_scale: NOISE_SCALE,
+            noise_wall_threshold: NOISE_WALL_THRESHOLD,
+            cave_fill_probability: CAVE_FILL_PROBABILITY,
+            cave_smoothing_passes: CAVE_SMOOTHING_PASSES,
+            cave_birth_limit: CAVE_BIRTH_LIMIT,
+            cave_survival_limit: CAVE_SURVIVAL_LIMIT,
+            rooms_min_leaf_size: BSP_MIN_LEAF_SIZE,
+            rooms_min_room_size: BSP_MIN_ROOM_SIZE,
+            connectivity: ConnectivityMode::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnemiesConfig {
+    pub count: usize,
+    pub min_distance_from_player: u32,
+    pub max_density: f64,
+    pub max_spawn_attempts: u32,
+}
+
+impl Default for EnemiesConfig {
+    fn default() -> Self {
+        EnemiesConfig {
+            count: ENEMY_COUNT,
+            min_distance_from_player: ENEMY_MIN_DISTANCE_FROM_PLAYER,
+            max_density: ENEMY_MAX_DENSITY,
+            max_spawn_attempts: ENEMY_SPAWN_ATTEMPTS,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ItemsConfig {
+    pub count: usize,
+    pub max_spawn_attempts: u32,
+}
+
+impl Default for ItemsConfig {
+    fn default() -> Self {
+        ItemsConfig {
+            count: ITEM_COUNT,
+            max_spawn_attempts: ITEM_SPAWN_ATTEMPTS,
+        }
+    }
+}
+
+/// The keys that control the game.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    pub north: KeyCode,
+    pub south: KeyCode,
+    pub west: KeyCode,
+    pub east: KeyCode,
+    pub use_stairs: KeyCode,
+    pub pick_up: KeyCode,
+    pub inventory: KeyCode,
+    // Drops the item selected in the inventory
+    pub drop_item: KeyCode,
+    // Uses the item selected in the inventory, or the item being aimed
+    pub use_item: KeyCode,
+    pub scroll_messages_up: KeyCode,
+    pub scroll_messages_down: KeyCode,
+    // Shows more of the message log at once
+    pub message_history: KeyCode,
+    pub quicksave: KeyCode,
+    pub quickload: KeyCode,
+    pub pause_replay: KeyCode,
+    pub step_replay: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            north: KeyCode::Up,
+            south: KeyCode::Down,
+            west: KeyCode::Left,
+            east: KeyCode::Right,
+            use_stairs: KeyCode::Return,
+            pick_up: KeyCode::G,
+            inventory: KeyCode::I,
+            drop_item: KeyCode::Back,
+            use_item: KeyCode::U,
+            scroll_messages_up: KeyCode::PageUp,
+            scroll_messages_down: KeyCode::PageDown,
+            message_history: KeyCode::M,
+            quicksave: KeyCode::F5,
+            quickload: KeyCode::F9,
+            pause_replay: KeyCode::Space,
+            step_replay: KeyCode::Period,
+        }
+    }
+}
+
+impl KeyBindings {
+    fn named_keys(&self) -> Vec<(&'static str, KeyCode)> {
+        vec![
+            ("north", self.north),
+            ("south", self.south),
+            ("west", self.west),
+            ("east", self.east),
+            ("use_stairs", self.use_stairs),
+            ("pick_up", self.pick_up),
+            ("inventory", self.inventory),
+            ("drop_item", self.drop_item),
+            ("use_item", self.use_item),
+            ("scroll_messages_up", self.scroll_messages_up),
+            ("scroll_messages_down", self.scroll_messages_down),
+            ("message_history", self.message_history),
+            ("quicksave", self.quicksave),
+            ("quickload", self.quickload),
+            ("pause_replay", self.pause_replay),
+            ("step_replay", self.step_replay),
+        ]
+    }
+}
+
+impl GameConfig {
+    /// Read the configuration from a file, then apply any overrides from the environment.
+    ///
+    /// The default configuration is used if `path` is `None` and the default file doesn't
+    /// exist.
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let mut config = match path {
+            Some(path) => Self::read(path)?,
+            None if Path::new(CONFIG_PATH).exists() => Self::read(Path::new(CONFIG_PATH))?,
+            None => GameConfig::default(),
+        };
+        config.apply_environment()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|error| format!("Couldn't read {}: {}", path.display(), error))?;
+        ron::de::from_str(&contents)
+            .map_err(|error| format!("Couldn't parse {}: {}", path.display(), error))
+    }
+
+    fn apply_environment(&mut self) -> Result<(), String> {
+        if let Ok(size) = std::env::var(MAP_SIZE_ENV_VAR) {
+            let dimensions: MapDimensions = size.parse()?;
+            self.map.width = dimensions.width;
+            self.map.height = dimensions.height;
+        }
+        if let Ok(generator) = std::env::var(GENERATOR_ENV_VAR) {
+            self.map.generator = generator;
+        }
+        Ok(())
+    }
+
+    /// Check the values make sense together, naming the first field that doesn't.
+    pub fn validate(&self) -> Result<(), String> {
+        let check = |is_valid: bool, field: &str, requirement: &str| {
+            if is_valid {
+                Ok(())
+            } else {
+                Err(format!("Invalid config: {} {}.", field, requirement))
+            }
+        };
+
+        check(self.window.width > 0.0, "window.width", "must be positive")?;
+        check(
+            self.window.height > 0.0,
+            "window.height",
+            "must be positive",
+        )?;
+
+        check(self.map.width > 0, "map.width", "must be positive")?;
+        check(self.map.height > 0, "map.height", "must be positive")?;
+        let generators = self.generators();
+        check(
+            generators.get(&self.map.generator).is_some(),
+            "map.generator",
+            &format!("must be one of {:?}", generators.names()),
+        )?;
+
+        let worldgen = &self.worldgen;
+        check(
+            worldgen.noise_scale > 0.0,
+            "worldgen.noise_scale",
+            "must be positive",
+        )?;
+        check(
+            (-1.0..=1.0).contains(&worldgen.noise_wall_threshold),
+            "worldgen.noise_wall_threshold",
+            "must be between -1 and 1",
+        )?;
+        check(
+            (0.0..=1.0).contains(&worldgen.cave_fill_probability),
+            "worldgen.cave_fill_probability",
+            "must be between 0 and 1",
+        )?;
+        check(
+            worldgen.cave_birth_limit <= 8,
+            "worldgen.cave_birth_limit",
+            "must be at most 8, the number of neighbours of a tile",
+        )?;
+        check(
+            worldgen.cave_survival_limit <= 8,
+            "worldgen.cave_survival_limit",
+            "must be at most 8, the number of neighbours of a tile",
+        )?;
+        check(
+            worldgen.rooms_min_room_size > 0,
+            "worldgen.rooms_min_room_size",
+            "must be positive",
+        )?;
+        check(
+            worldgen.rooms_min_leaf_size >= worldgen.rooms_min_room_size + 2,
+            "worldgen.rooms_min_leaf_size",
+            "must leave space for the smallest room and a wall on each side",
+        )?;
+        if self.map.generator == "rooms" {
+            check(
+                self.map.width >= worldgen.rooms_min_leaf_size
+                    && self.map.height >= worldgen.rooms_min_leaf_size,
+                "map.width and map.height",
+                "must be at least worldgen.rooms_min_leaf_size for the rooms generator",
+            )?;
+        }
+
+        check(
+            self.enemies.max_density >= 0.0,
+            "enemies.max_density",
+            "must not be negative",
+        )?;
+
+        let keys = self.key_bindings.named_keys();
+        for (i, (name, key)) in keys.iter().enumerate() {
+            if let Some((other_name, _)) = keys[..i].iter().find(|(_, other)| other == key) {
+                return Err(format!(
+                    "Invalid config: key_bindings.{} and key_bindings.{} are both bound to {:?}.",
+                    other_name, name, key
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The registered generators, set up with this configuration's parameters.
+    pub fn generators(&self) -> MapGenerators {
+        let worldgen = &self.worldgen;
+        let mut generators = MapGenerators::default();
+        generators.register(
+            "noise",
+            NoiseGenerator {
+                scale: worldgen.noise_scale,
+                wall_threshold: worldgen.noise_wall_threshold,
+            },
+        );
+        generators.register(
+            "caves",
+            CaveGenerator {
+                fill_probability: worldgen.cave_fill_probability,
+                smoothing_passes: worldgen.cave_smoothing_passes,
+                birth_limit: worldgen.cave_birth_limit,
+                survival_limit: worldgen.cave_survival_limit,
+            },
+        );
+        generators.register(
+            "rooms",
+            RoomsGenerator {
+                min_leaf_size: worldgen.rooms_min_leaf_size,
+                min_room_size: worldgen.rooms_min_room_size,
+            },
+        );
+        generators
+    }
+
+    pub fn window_descriptor(&self) -> WindowDescriptor {
+        WindowDescriptor {
+            width: self.window.width,
+            height: self.window.height,
+            title: self.window.title.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Insert the resources the game reads its settings from, before any startup system runs.
+    pub fn insert_resources(&self, app: &mut AppBuilder) {
+        app.insert_resource(MapDimensions::new(self.map.width, self.map.height))
+            .insert_resource(SelectedGenerator(self.map.generator.clone()))
+            .insert_resource(self.generators())
+            .insert_resource(self.worldgen.connectivity)
+            // Kept so replays can record what the generators were set up with
+            .insert_resource(self.worldgen.clone())
+            .insert_resource(SpawnSettings {
+                enemy_count: self.enemies.count,
+                min_distance_from_player: self.enemies.min_distance_from_player,
+                max_density: self.enemies.max_density,
+                max_attempts: self.enemies.max_spawn_attempts,
+            })
+            .insert_resource(ItemSpawnSettings {
+                item_count: self.items.count,
+                max_attempts: self.items.max_spawn_attempts,
+            })
+            .insert_resource(self.key_bindings.clone());
+    }
+}
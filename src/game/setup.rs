@@ -1,22 +1,32 @@
 use bevy::prelude::*;
 use bevy_ecs_tilemap::prelude::*;
-use noise::{utils::*, Fbm};
 
-use crate::game::components::HaveUpdatedTilemap;
 use crate::game::config::{
-    CHUNK_SIZE, MAP_ID, N_CHUNKS_X, N_CHUNKS_Y, N_TEXTURES, OBJECTS_LAYER_ID, TILE_SIZE,
-    WALLS_LAYER_ID, WALL_TEXTURE_INDEX, WORLDGEN_SCALE,
+    BLOCKED_TARGETING_LAYER_ID, MAP_ID, N_TEXTURES, OBJECTS_LAYER_ID, OUT_OF_SIGHT_BRIGHTNESS,
+    REMEMBERED_WALLS_LAYER_ID, TARGETING_LAYER_ID, WALLS_LAYER_ID,
 };
+use crate::game::map::MapDimensions;
 
 pub fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut map_query: MapQuery,
+    dimensions: Res<MapDimensions>,
 ) {
-    // Load textures
+    // Load textures, tiles can't be tinted individually so each tint has its own material
     let texture_handle = asset_server.load("textures/textures.png");
-    let material_handle = materials.add(ColorMaterial::texture(texture_handle));
+    let material_handle = materials.add(ColorMaterial::texture(texture_handle.clone()));
+    let remembered_material_handle = materials.add(ColorMaterial::modulated_texture(
+        texture_handle.clone(),
+        Color::rgb(
+            OUT_OF_SIGHT_BRIGHTNESS,
+            OUT_OF_SIGHT_BRIGHTNESS,
+            OUT_OF_SIGHT_BRIGHTNESS,
+        ),
+    ));
+    let blocked_material_handle =
+        materials.add(ColorMaterial::modulated_texture(texture_handle, Color::RED));
 
     // Create map entity and component:
     let map_entity = commands.spawn().id();
@@ -24,72 +34,37 @@ pub fn setup(
 
     // Define the layer settings for each layer
     let default_layer_settings = LayerSettings::new(
-        UVec2::new(N_CHUNKS_X, N_CHUNKS_Y),
-        UVec2::new(CHUNK_SIZE, CHUNK_SIZE),
-        Vec2::new(TILE_SIZE, TILE_SIZE),
-        Vec2::new((N_TEXTURES as f32) * TILE_SIZE, TILE_SIZE),
+        dimensions.n_chunks(),
+        UVec2::new(dimensions.chunk_size, dimensions.chunk_size),
+        Vec2::new(dimensions.tile_size, dimensions.tile_size),
+        Vec2::new(
+            (N_TEXTURES as f32) * dimensions.tile_size,
+            dimensions.tile_size,
+        ),
     );
 
-    // Create the walls layer
-    let (mut walls_layer_builder, walls_layer_entity) = LayerBuilder::<TileBundle>::new(
-        &mut commands,
-        default_layer_settings.clone(),
-        MAP_ID,
-        WALLS_LAYER_ID,
-    );
-    // Required to keep track of layers for a map internally.
-    map.add_layer(&mut commands, WALLS_LAYER_ID, walls_layer_entity);
-
-    // World generation
-    {
-        // Construct a noise generator
-        let noise_map = PlaneMapBuilder::new(&Fbm::new())
-            .set_size(
-                (N_CHUNKS_X * CHUNK_SIZE) as usize,
-                (N_CHUNKS_Y * CHUNK_SIZE) as usize,
-            )
-            .set_x_bounds(
-                -(N_CHUNKS_X as f64) / WORLDGEN_SCALE,
-                N_CHUNKS_X as f64 / WORLDGEN_SCALE,
-            )
-            .set_y_bounds(
-                -(N_CHUNKS_Y as f64) / WORLDGEN_SCALE,
-                N_CHUNKS_Y as f64 / WORLDGEN_SCALE,
-            )
-            .build();
-
-        // Use noise function to set walls
-        for i in 0..(N_CHUNKS_X * CHUNK_SIZE) {
-            for j in 0..(N_CHUNKS_Y * CHUNK_SIZE) {
-                let noise_value = noise_map.get_value(i as usize, j as usize);
-                if noise_value > 0.0 {
-                    let tile = Tile {
-                        texture_index: WALL_TEXTURE_INDEX,
-                        ..Default::default()
-                    };
-                    let _ = walls_layer_builder
-                        .set_tile(UVec2::new(i, j), tile.into())
-                        .expect("Couldn't set tile! :(");
-                }
-            }
-        }
+    let layers = [
+        // The walls are mirrored from the world map later
+        (WALLS_LAYER_ID, material_handle.clone()),
+        // Walls the player remembers but can't see are drawn darker
+        (REMEMBERED_WALLS_LAYER_ID, remembered_material_handle),
+        (OBJECTS_LAYER_ID, material_handle.clone()),
+        // Drawn over everything else while an item is being aimed, in red where it can't be
+        (TARGETING_LAYER_ID, material_handle),
+        (BLOCKED_TARGETING_LAYER_ID, blocked_material_handle),
+    ];
+    for (layer_id, material_handle) in layers {
+        let (layer_builder, layer_entity) = LayerBuilder::<TileBundle>::new(
+            &mut commands,
+            default_layer_settings.clone(),
+            MAP_ID,
+            layer_id,
+        );
+        // Required to keep track of layers for a map internally.
+        map.add_layer(&mut commands, layer_id, layer_entity);
+        let _ = map_query.build_layer(&mut commands, layer_builder, material_handle);
     }
 
-    // Build the walls layer
-    let _ = map_query.build_layer(&mut commands, walls_layer_builder, material_handle.clone());
-
-    // Create the objects layer
-    let (objects_layer_builder, objects_layer_entity) = LayerBuilder::<TileBundle>::new(
-        &mut commands,
-        default_layer_settings,
-        MAP_ID,
-        OBJECTS_LAYER_ID,
-    );
-    // Required to keep track of layers for a map internally.
-    map.add_layer(&mut commands, OBJECTS_LAYER_ID, objects_layer_entity);
-    // Build the objects layer
-    let _ = map_query.build_layer(&mut commands, objects_layer_builder, material_handle);
-
     // Create map
     commands
         .entity(map_entity)
@@ -97,6 +72,6 @@ pub fn setup(
         .insert(Transform::from_xyz(-128.0, -128.0, 0.0))
         .insert(GlobalTransform::default());
 
-    // Add entity to monitor whether we have updated the tilemap
-    commands.spawn().insert(HaveUpdatedTilemap(false));
+    // The interface is drawn over the tilemap by its own camera
+    commands.spawn_bundle(UiCameraBundle::default());
 }
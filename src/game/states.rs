@@ -1,17 +1,29 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::game::components::{GoingToTakeAction, HaveUpdatedTilemap, PlayerTag};
+use crate::game::components::{Energy, GoingToTakeAction, Health, PlayerTag};
+use crate::game::scheduler::is_ready;
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum GameState {
     PlayerTurn,
     EnemyTurn,
     TakeAction,
-    UpdateTilemap,
+    // Every action has been resolved
+    EndTurn,
+    // The player has died
+    GameOver,
 }
 
+/// The number of turns the player has taken.
+///
+/// Other entities may act several times in between, or not at all, depending on their speed.
+#[derive(Default)]
+pub struct TurnCount(pub u32);
+
 pub fn end_player_turn(
     player_query: Query<&GoingToTakeAction, With<PlayerTag>>,
+    mut turn_count: ResMut<TurnCount>,
     mut game_state: ResMut<State<GameState>>,
 ) {
     let has_player_decided = player_query
@@ -20,17 +32,20 @@ pub fn end_player_turn(
 
     if has_player_decided {
         // println!("Transitioning from PlayerTurn to EnemyTurn.");
+        turn_count.0 += 1;
         game_state.replace(GameState::EnemyTurn).unwrap();
     }
 }
 
 pub fn end_enemy_turn(
-    nonplayer_entities_query: Query<&GoingToTakeAction, Without<PlayerTag>>,
+    nonplayer_entities_query: Query<(&GoingToTakeAction, &Energy), Without<PlayerTag>>,
     mut game_state: ResMut<State<GameState>>,
 ) {
+    // Only the entities with enough energy act
     let have_all_entities_decided = nonplayer_entities_query
         .iter()
-        .all(|decision| decision.action.is_some());
+        .filter(|(_, energy)| is_ready(energy))
+        .all(|(decision, _)| decision.action.is_some());
 
     if have_all_entities_decided {
         // println!("Transitioning from EnemyTurn to TakeAction.");
@@ -47,22 +62,27 @@ pub fn end_action_state(
         .all(|decision| decision.action.is_none());
 
     if have_all_entities_moved {
-        // println!("Transitioning from TakeAction to UpdateTilemap.");
-        game_state.replace(GameState::UpdateTilemap).unwrap();
+        // println!("Transitioning from TakeAction to EndTurn.");
+        game_state.replace(GameState::EndTurn).unwrap();
     }
 }
 
-pub fn end_update_tilemap_state(
-    have_updated_tilemap_query: Query<&HaveUpdatedTilemap>,
+pub fn end_turn(
+    player_query: Query<(&Health, &Energy), With<PlayerTag>>,
     mut game_state: ResMut<State<GameState>>,
 ) {
-    let have_updated_tilemap = have_updated_tilemap_query
-        .single()
-        .expect("Cannot have more than one HaveUpdatedTilemap entity.")
-        .0;
+    let is_player_alive = player_query.iter().any(|(health, _)| health.current > 0);
+    let is_player_ready = player_query.iter().any(|(_, energy)| is_ready(energy));
 
-    if have_updated_tilemap {
-        // println!("Transitioning from TakeAction to UpdateTilemap.");
+    if is_player_alive && is_player_ready {
+        // println!("Transitioning from EndTurn to PlayerTurn.");
         game_state.replace(GameState::PlayerTurn).unwrap();
+    } else if is_player_alive {
+        // The player doesn't have enough energy to act yet, so skip to the enemies
+        // println!("Transitioning from EndTurn to EnemyTurn.");
+        game_state.replace(GameState::EnemyTurn).unwrap();
+    } else {
+        log::info!("The player has died.");
+        game_state.replace(GameState::GameOver).unwrap();
     }
 }
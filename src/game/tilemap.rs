@@ -1,33 +1,138 @@
 use bevy::prelude::*;
 use bevy_ecs_tilemap::prelude::*;
 
-use crate::game::components::{Drawable, Position};
-use crate::game::config::{CHUNK_SIZE, MAP_ID, N_CHUNKS_X, N_CHUNKS_Y, OBJECTS_LAYER_ID};
+use crate::game::components::{Drawable, Inventory, MapMemory, PlayerTag, Position, Viewshed};
+use crate::game::config::{
+    BLOCKED_TARGETING_LAYER_ID, DOWN_STAIRS_TEXTURE_INDEX, MAP_ID, OBJECTS_LAYER_ID,
+    REMEMBERED_WALLS_LAYER_ID, TARGETING_LAYER_ID, TARGET_TEXTURE_INDEX, UP_STAIRS_TEXTURE_INDEX,
+    WALLS_LAYER_ID, WALL_TEXTURE_INDEX,
+};
+use crate::game::inventory_screen::InventoryScreen;
+use crate::game::map::{TileKind, WorldMap};
+use crate::game::occupancy::Occupancy;
 
-use crate::game::components::HaveUpdatedTilemap;
-
-pub fn update_tilemap(
-    drawable_entities_query: Query<(&Position, &Drawable)>,
-    mut have_updated_tilemap_query: Query<&mut HaveUpdatedTilemap>,
+pub fn update_walls(
+    player_viewshed_query: Query<(&Viewshed, Option<&MapMemory>), With<PlayerTag>>,
+    changed_player_viewshed_query: Query<(), (With<PlayerTag>, Changed<Viewshed>)>,
+    world_map: Res<WorldMap>,
     mut map_query: MapQuery,
     mut commands: Commands,
 ) {
-    for x in 0..(CHUNK_SIZE * N_CHUNKS_X) {
-        for y in 0..(CHUNK_SIZE * N_CHUNKS_Y) {
+    // Only mirror the world map when it has been generated or replaced, or the player can see
+    // something new
+    let has_player_view_changed = changed_player_viewshed_query.iter().next().is_some();
+    if !world_map.is_changed() && !has_player_view_changed {
+        return;
+    }
+    let (player_viewshed, player_map_memory) = match player_viewshed_query.iter().next() {
+        Some((viewshed, map_memory)) => (Some(viewshed), map_memory),
+        None => (None, None),
+    };
+
+    for x in 0..world_map.width {
+        for y in 0..world_map.height {
             let tile_position = UVec2::new(x, y);
-            if map_query
-                .get_tile_entity(tile_position, MAP_ID, OBJECTS_LAYER_ID)
-                .is_ok()
-            {
-                let _ = map_query
-                    .despawn_tile(&mut commands, tile_position, MAP_ID, OBJECTS_LAYER_ID)
-                    .unwrap_or_else(|_| panic!("Couldn't despawn tile at ({},{}).", x, y));
-                map_query.notify_chunk_for_tile(tile_position, MAP_ID, OBJECTS_LAYER_ID);
+            // Tiles the player hasn't explored yet aren't drawn at all
+            let is_explored =
+                player_map_memory.map_or(true, |memory| memory.is_explored(Position { x, y }));
+            // Visible tiles are drawn normally, and explored tiles that aren't visible are drawn
+            // darker as they are only remembered
+            let drawn_layer_id = if is_visible(player_viewshed, Position { x, y }) {
+                WALLS_LAYER_ID
+            } else {
+                REMEMBERED_WALLS_LAYER_ID
+            };
+            // Floor is left empty
+            let texture_index = match world_map.get(x, y) {
+                TileKind::Floor => None,
+                TileKind::Wall => Some(WALL_TEXTURE_INDEX),
+                TileKind::DownStairs => Some(DOWN_STAIRS_TEXTURE_INDEX),
+                TileKind::UpStairs => Some(UP_STAIRS_TEXTURE_INDEX),
+            };
+
+            for layer_id in [WALLS_LAYER_ID, REMEMBERED_WALLS_LAYER_ID] {
+                let has_wall_tile = map_query
+                    .get_tile_entity(tile_position, MAP_ID, layer_id)
+                    .is_ok();
+                let is_drawn = is_explored && layer_id == drawn_layer_id;
+                if let (Some(texture_index), true) = (texture_index, is_drawn) {
+                    let _ = map_query
+                        .set_tile(
+                            &mut commands,
+                            tile_position,
+                            Tile {
+                                texture_index,
+                                ..Default::default()
+                            },
+                            MAP_ID,
+                            layer_id,
+                        )
+                        .unwrap_or_else(|_| panic!("Couldn't set the wall tile at ({},{}).", x, y));
+                } else if has_wall_tile {
+                    map_query
+                        .despawn_tile(&mut commands, tile_position, MAP_ID, layer_id)
+                        .unwrap_or_else(|_| panic!("Couldn't despawn tile at ({},{}).", x, y));
+                }
+                map_query.notify_chunk_for_tile(tile_position, MAP_ID, layer_id);
             }
         }
     }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_tilemap(
+    drawable_entities_query: Query<(Entity, &Position, &Drawable, Option<&PlayerTag>)>,
+    player_viewshed_query: Query<&Viewshed, With<PlayerTag>>,
+    changed_positions_query: Query<(), Changed<Position>>,
+    changed_player_viewshed_query: Query<(), (With<PlayerTag>, Changed<Viewshed>)>,
+    removed_positions: RemovedComponents<Position>,
+    occupancy: Res<Occupancy>,
+    mut drawn_tiles: Local<Vec<UVec2>>,
+    mut map_query: MapQuery,
+    mut commands: Commands,
+) {
+    // Only redraw the objects when something has moved, appeared or disappeared, or the player
+    // can see something new
+    let have_objects_changed = changed_positions_query.iter().next().is_some()
+        || changed_player_viewshed_query.iter().next().is_some()
+        || removed_positions.iter().next().is_some();
+    if !have_objects_changed {
+        return;
+    }
+    let player_viewshed = player_viewshed_query.iter().next();
+
+    // Clear the tiles drawn last time
+    for tile_position in drawn_tiles.drain(..) {
+        if map_query
+            .get_tile_entity(tile_position, MAP_ID, OBJECTS_LAYER_ID)
+            .is_ok()
+        {
+            map_query
+                .despawn_tile(&mut commands, tile_position, MAP_ID, OBJECTS_LAYER_ID)
+                .unwrap_or_else(|_| {
+                    panic!(
+                        "Couldn't despawn tile at ({},{}).",
+                        tile_position.x, tile_position.y
+                    )
+                });
+            map_query.notify_chunk_for_tile(tile_position, MAP_ID, OBJECTS_LAYER_ID);
+        }
+    }
+
+    for (entity, entity_position, texture_index, player) in drawable_entities_query.iter() {
+        // Only draw other entities when the player can see them
+        if player.is_none() && !is_visible(player_viewshed, *entity_position) {
+            continue;
+        }
+        // Entities that block the tile, such as creatures, are drawn over anything else on it,
+        // such as the items they are standing on
+        let is_covered = occupancy
+            .blocker_at(*entity_position)
+            .map_or(false, |blocker| blocker != entity);
+        if is_covered {
+            continue;
+        }
 
-    for (entity_position, texture_index) in drawable_entities_query.iter() {
         let tile_position = UVec2::new(entity_position.x, entity_position.y);
         let _ = map_query
             .set_tile(
@@ -47,17 +152,91 @@ pub fn update_tilemap(
                 )
             });
         map_query.notify_chunk_for_tile(tile_position, MAP_ID, OBJECTS_LAYER_ID);
+        drawn_tiles.push(tile_position);
     }
+}
 
-    let mut have_updated_tilemap = have_updated_tilemap_query
-        .single_mut()
-        .expect("Cannot have more than one HaveUpdatedTilemap entity.");
-    have_updated_tilemap.0 = true;
+/// Mark the tiles an item being aimed would hit, in red if it can't be aimed there.
+pub fn update_targeting(
+    inventory_screen: Res<InventoryScreen>,
+    player_query: Query<(&Position, &Viewshed, &Inventory), With<PlayerTag>>,
+    world_map: Res<WorldMap>,
+    mut drawn_tiles: Local<Vec<(UVec2, u16)>>,
+    mut map_query: MapQuery,
+    mut commands: Commands,
+) {
+    if !inventory_screen.is_changed() {
+        return;
+    }
+
+    // Clear the tiles drawn last time
+    for (tile_position, layer_id) in drawn_tiles.drain(..) {
+        if map_query
+            .get_tile_entity(tile_position, MAP_ID, layer_id)
+            .is_ok()
+        {
+            map_query
+                .despawn_tile(&mut commands, tile_position, MAP_ID, layer_id)
+                .unwrap_or_else(|_| {
+                    panic!(
+                        "Couldn't despawn tile at ({},{}).",
+                        tile_position.x, tile_position.y
+                    )
+                });
+            map_query.notify_chunk_for_tile(tile_position, MAP_ID, layer_id);
+        }
+    }
+
+    let (aim, (player_position, viewshed, inventory)) =
+        match (&inventory_screen.aim, player_query.iter().next()) {
+            (Some(aim), Some(player)) => (aim, player),
+            _ => return,
+        };
+    let targeting = match inventory
+        .items
+        .get(aim.item_index)
+        .and_then(|item| item.consumable.as_ref())
+    {
+        Some(consumable) => consumable.targeting,
+        None => return,
+    };
+    // Targets that can't be aimed at are drawn on a layer tinted red
+    let layer_id = if targeting.is_valid_target(*player_position, viewshed, aim.target) {
+        TARGETING_LAYER_ID
+    } else {
+        BLOCKED_TARGETING_LAYER_ID
+    };
+
+    // Always mark the target itself, even if it's a wall
+    let mut tiles = targeting.affected_tiles(&world_map, *player_position, Some(aim.target));
+    if !tiles.contains(&aim.target) {
+        tiles.push(aim.target);
+    }
+    for position in tiles {
+        let tile_position = UVec2::new(position.x, position.y);
+        let _ = map_query
+            .set_tile(
+                &mut commands,
+                tile_position,
+                Tile {
+                    texture_index: TARGET_TEXTURE_INDEX,
+                    ..Default::default()
+                },
+                MAP_ID,
+                layer_id,
+            )
+            .unwrap_or_else(|_| {
+                panic!(
+                    "Couldn't set the target tile at ({},{}).",
+                    position.x, position.y
+                )
+            });
+        map_query.notify_chunk_for_tile(tile_position, MAP_ID, layer_id);
+        drawn_tiles.push((tile_position, layer_id));
+    }
 }
 
-pub fn is_in_bounds(position: IVec2) -> bool {
-    position.x >= 0
-        && position.y >= 0
-        && position.x < (CHUNK_SIZE * N_CHUNKS_X) as i32
-        && position.y < (CHUNK_SIZE * N_CHUNKS_Y) as i32
+/// Whether the player can see the position, everything is visible if the player can't see at all.
+fn is_visible(player_viewshed: Option<&Viewshed>, position: Position) -> bool {
+    player_viewshed.map_or(true, |viewshed| viewshed.visible_tiles.contains(&position))
 }
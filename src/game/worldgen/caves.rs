@@ -0,0 +1,87 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::game::config::{
+    CAVE_BIRTH_LIMIT, CAVE_FILL_PROBABILITY, CAVE_SMOOTHING_PASSES, CAVE_SURVIVAL_LIMIT,
+};
+use crate::game::map::{TileKind, WorldMap};
+use crate::game::rng::GameRng;
+use crate::game::worldgen::{GeneratedMap, MapGenerator};
+
+/// Grows organic caves from random noise with a cellular automaton.
+pub struct CaveGenerator {
+    // Chance of each tile starting as a wall
+    pub fill_probability: f64,
+    pub smoothing_passes: u32,
+    // A floor tile becomes a wall if at least this many of its neighbours are walls
+    pub birth_limit: u32,
+    // A wall tile stays a wall if at least this many of its neighbours are walls
+    pub survival_limit: u32,
+}
+
+impl Default for CaveGenerator {
+    fn default() -> Self {
+        CaveGenerator {
+            fill_probability: CAVE_FILL_PROBABILITY,
+            smoothing_passes: CAVE_SMOOTHING_PASSES,
+            birth_limit: CAVE_BIRTH_LIMIT,
+            survival_limit: CAVE_SURVIVAL_LIMIT,
+        }
+    }
+}
+
+impl MapGenerator for CaveGenerator {
+    fn generate(&self, seed: u64, width: u32, height: u32) -> GeneratedMap {
+        let mut rng = GameRng::from_seed(seed);
+        let mut world_map = WorldMap::new(width, height);
+
+        // Start with random noise
+        for i in 0..width {
+            for j in 0..height {
+                if rng.gen_bool(self.fill_probability) {
+                    world_map.set(i, j, TileKind::Wall);
+                }
+            }
+        }
+
+        // Smooth the noise into caves
+        for _ in 0..self.smoothing_passes {
+            let previous_map = world_map.clone();
+            for i in 0..width {
+                for j in 0..height {
+                    let n_wall_neighbours = count_wall_neighbours(&previous_map, i, j);
+                    let limit = if previous_map.is_wall(i, j) {
+                        self.survival_limit
+                    } else {
+                        self.birth_limit
+                    };
+                    let kind = if n_wall_neighbours >= limit {
+                        TileKind::Wall
+                    } else {
+                        TileKind::Floor
+                    };
+                    world_map.set(i, j, kind);
+                }
+            }
+        }
+
+        GeneratedMap::from_world_map(world_map)
+    }
+}
+
+/// Count the walls in the eight tiles around a tile, treating the edge of the map as wall.
+fn count_wall_neighbours(world_map: &WorldMap, x: u32, y: u32) -> u32 {
+    let mut n_walls = 0;
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            if dx == 0 && dy == 0 {
+                continue;
+            }
+            let neighbour = IVec2::new(x as i32 + dx, y as i32 + dy);
+            if !world_map.is_walkable(neighbour) {
+                n_walls += 1;
+            }
+        }
+    }
+    n_walls
+}
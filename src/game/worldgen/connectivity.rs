@@ -0,0 +1,267 @@
+use std::collections::{HashSet, VecDeque};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::Position;
+use crate::game::map::{Region, TileKind, WorldMap};
+use crate::game::pathfinding::manhattan_distance;
+use crate::game::worldgen::{carve_line, line_tiles, GeneratedMap, NEIGHBOUR_DELTAS};
+
+/// How to deal with floor that can't be reached from the player's spawn.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ConnectivityMode {
+    // Fill unreachable floor in with walls
+    RemoveUnreachable,
+    // Dig corridors joining every region to the spawn's region
+    #[default]
+    Tunnel,
+}
+
+/// Make every floor tile of the map reachable from the player's spawn, which becomes the first
+/// spawn point of the layout.
+pub fn connect(generated_map: &mut GeneratedMap, mode: ConnectivityMode) {
+    let world_map = &mut generated_map.world_map;
+    let layout = &mut generated_map.layout;
+
+    let mut regions = connected_regions(world_map);
+    if regions.is_empty() {
+        // Make sure there is somewhere to stand
+        let centre = Position {
+            x: world_map.width / 2,
+            y: world_map.height / 2,
+        };
+        world_map.set(centre.x, centre.y, TileKind::Floor);
+        regions = connected_regions(world_map);
+    }
+
+    // Spawn at the generator's preferred spawn point if it is floor, otherwise at the centre of
+    // the largest region, the first one found if several are as large
+    let spawn = layout
+        .spawn_points
+        .iter()
+        .copied()
+        .find(|position| !world_map.is_wall(position.x, position.y))
+        .unwrap_or_else(|| {
+            let largest_size = regions.iter().map(|region| region.tiles.len()).max();
+            regions
+                .iter()
+                .find(|region| Some(region.tiles.len()) == largest_size)
+                .and_then(Region::centre)
+                .unwrap()
+        });
+
+    let (main_regions, other_regions): (Vec<Region>, Vec<Region>) = regions
+        .into_iter()
+        .partition(|region| region.tiles.contains(&spawn));
+    let mut reachable: HashSet<Position> = main_regions
+        .into_iter()
+        .flat_map(|region| region.tiles)
+        .collect();
+
+    for region in other_regions {
+        match mode {
+            ConnectivityMode::RemoveUnreachable => {
+                for position in region.tiles {
+                    world_map.set(position.x, position.y, TileKind::Wall);
+                }
+            }
+            ConnectivityMode::Tunnel => {
+                // Dig from the region to the closest tile already reachable
+                let start = region.tiles[0];
+                let end = *reachable
+                    .iter()
+                    .min_by_key(|position| (manhattan_distance(start, **position), **position))
+                    .unwrap();
+                let corner = Position {
+                    x: end.x,
+                    y: start.y,
+                };
+                carve_line(start, corner, world_map);
+                carve_line(corner, end, world_map);
+                reachable.extend(region.tiles);
+                reachable.extend(line_tiles(start, corner));
+                reachable.extend(line_tiles(corner, end));
+            }
+        }
+    }
+
+    // Forget about anything that was filled in
+    layout
+        .spawn_points
+        .retain(|position| reachable.contains(position));
+    layout.spawn_points.retain(|position| *position != spawn);
+    layout.spawn_points.insert(0, spawn);
+    for region in layout.regions.iter_mut() {
+        region
+            .tiles
+            .retain(|position| !world_map.is_wall(position.x, position.y));
+    }
+    layout.regions.retain(|region| !region.tiles.is_empty());
+}
+
+/// Split the floor of the map into groups of tiles that can be walked between, using a flood
+/// fill from each tile that isn't in a group yet.
+pub fn connected_regions(world_map: &WorldMap) -> Vec<Region> {
+    let mut visited = vec![false; (world_map.width * world_map.height) as usize];
+    let mut regions = Vec::new();
+
+    for y in 0..world_map.height {
+        for x in 0..world_map.width {
+            if world_map.is_wall(x, y) || visited[(y * world_map.width + x) as usize] {
+                continue;
+            }
+
+            let mut tiles = Vec::new();
+            let mut frontier = VecDeque::new();
+            visited[(y * world_map.width + x) as usize] = true;
+            frontier.push_back(Position { x, y });
+
+            while let Some(position) = frontier.pop_front() {
+                tiles.push(position);
+                for delta in NEIGHBOUR_DELTAS.iter() {
+                    let neighbour = IVec2::new(position.x as i32, position.y as i32) + *delta;
+                    if !world_map.is_walkable(neighbour) {
+                        continue;
+                    }
+                    let index =
+                        (neighbour.y as u32 * world_map.width + neighbour.x as u32) as usize;
+                    if !visited[index] {
+                        visited[index] = true;
+                        frontier.push_back(Position {
+                            x: neighbour.x as u32,
+                            y: neighbour.y as u32,
+                        });
+                    }
+                }
+            }
+
+            regions.push(Region { tiles });
+        }
+    }
+
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::worldgen::caves::CaveGenerator;
+    use crate::game::worldgen::noise::NoiseGenerator;
+    use crate::game::worldgen::rooms::RoomsGenerator;
+    use crate::game::worldgen::MapGenerator;
+
+    const MODES: [ConnectivityMode; 2] = [
+        ConnectivityMode::RemoveUnreachable,
+        ConnectivityMode::Tunnel,
+    ];
+
+    fn floor_tiles(world_map: &WorldMap) -> Vec<Position> {
+        (0..world_map.width)
+            .flat_map(|x| (0..world_map.height).map(move |y| Position { x, y }))
+            .filter(|position| !world_map.is_wall(position.x, position.y))
+            .collect()
+    }
+
+    /// Check every floor tile can be reached from the first spawn point.
+    fn assert_connected(generated_map: &GeneratedMap) {
+        let regions = connected_regions(&generated_map.world_map);
+        assert_eq!(regions.len(), 1);
+        let spawn = generated_map.layout.spawn_points[0];
+        assert!(regions[0].tiles.contains(&spawn));
+    }
+
+    #[test]
+    fn finds_each_separate_region() {
+        let generated_map = GeneratedMap::from_world_map(WorldMap::from_rows(&[
+            "..#...#.", //
+            "..#...##", "###.....",
+        ]));
+
+        let mut sizes: Vec<usize> = connected_regions(&generated_map.world_map)
+            .iter()
+            .map(|region| region.tiles.len())
+            .collect();
+        sizes.sort_unstable();
+
+        assert_eq!(sizes, vec![1, 4, 11]);
+    }
+
+    #[test]
+    fn tunnelling_keeps_every_floor_tile() {
+        let mut generated_map = GeneratedMap::from_world_map(WorldMap::from_rows(&[
+            "..#####..", //
+            "..#####..",
+            "#########",
+            "....#....",
+        ]));
+        let floor_before = floor_tiles(&generated_map.world_map);
+
+        connect(&mut generated_map, ConnectivityMode::Tunnel);
+
+        assert_connected(&generated_map);
+        for position in floor_before {
+            assert!(!generated_map.world_map.is_wall(position.x, position.y));
+        }
+    }
+
+    #[test]
+    fn removing_unreachable_floor_keeps_the_spawn_region() {
+        let mut generated_map = GeneratedMap::from_world_map(WorldMap::from_rows(&[
+            "..#####..", //
+            "..#####..",
+            "#########",
+            "....#....",
+        ]));
+        let spawn = Position { x: 0, y: 0 };
+        generated_map.layout.spawn_points = vec![spawn];
+
+        connect(&mut generated_map, ConnectivityMode::RemoveUnreachable);
+
+        assert_connected(&generated_map);
+        assert_eq!(generated_map.layout.spawn_points[0], spawn);
+        assert_eq!(floor_tiles(&generated_map.world_map).len(), 4);
+    }
+
+    #[test]
+    fn solid_maps_get_somewhere_to_stand() {
+        for mode in MODES {
+            let mut generated_map =
+                GeneratedMap::from_world_map(WorldMap::from_rows(&["#####", "#####", "#####"]));
+
+            connect(&mut generated_map, mode);
+
+            assert_connected(&generated_map);
+            assert_eq!(
+                generated_map.layout.spawn_points[0],
+                Position { x: 2, y: 1 }
+            );
+        }
+    }
+
+    #[test]
+    fn generated_maps_are_connected_in_every_mode() {
+        let generators: [&dyn MapGenerator; 3] = [
+            &CaveGenerator::default(),
+            &NoiseGenerator::default(),
+            &RoomsGenerator::default(),
+        ];
+        for generator in generators {
+            for seed in 0..5 {
+                for mode in MODES {
+                    let mut generated_map = generator.generate(seed, 60, 40);
+
+                    connect(&mut generated_map, mode);
+
+                    assert_connected(&generated_map);
+                    // Regions only keep the tiles that are still floor
+                    for region in generated_map.layout.regions.iter() {
+                        for position in region.tiles.iter() {
+                            assert!(!generated_map.world_map.is_wall(position.x, position.y));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use bevy::math::const_ivec2;
+use bevy::prelude::*;
+use rand::RngCore;
+
+use crate::game::components::Position;
+use crate::game::config::DEFAULT_GENERATOR;
+use crate::game::levels::Depth;
+use crate::game::map::{MapDimensions, MapLayout, TileKind, WorldMap};
+use crate::game::rng::GameRng;
+
+pub mod caves;
+pub mod connectivity;
+pub mod noise;
+pub mod rooms;
+pub mod stairs;
+
+// Environment variable that can be used to choose the map generator
+pub const GENERATOR_ENV_VAR: &str = "DINK_GENERATOR";
+
+// The four directions entities can walk in
+pub const NEIGHBOUR_DELTAS: [IVec2; 4] = [
+    const_ivec2!([1, 0]),
+    const_ivec2!([-1, 0]),
+    const_ivec2!([0, 1]),
+    const_ivec2!([0, -1]),
+];
+
+/// An algorithm that lays out the tiles of a map.
+///
+/// Generators only produce a grid of tiles and some metadata about it, so they can be swapped
+/// without touching spawning or rendering.
+pub trait MapGenerator: Send + Sync {
+    /// Generate a map, the same seed and dimensions must always give the same map.
+    fn generate(&self, seed: u64, width: u32, height: u32) -> GeneratedMap;
+}
+
+pub struct GeneratedMap {
+    pub world_map: WorldMap,
+    pub layout: MapLayout,
+}
+
+impl GeneratedMap {
+    /// A map without any metadata.
+    pub fn from_world_map(world_map: WorldMap) -> Self {
+        GeneratedMap {
+            world_map,
+            layout: MapLayout::default(),
+        }
+    }
+}
+
+/// All the generators that can be chosen by name.
+pub struct MapGenerators {
+    generators: Vec<(String, Box<dyn MapGenerator>)>,
+}
+
+impl Default for MapGenerators {
+    fn default() -> Self {
+        let mut generators = MapGenerators {
+            generators: Vec::new(),
+        };
+        generators.register("noise", noise::NoiseGenerator::default());
+        generators.register("caves", caves::CaveGenerator::default());
+        generators.register("rooms", rooms::RoomsGenerator::default());
+        generators
+    }
+}
+
+impl MapGenerators {
+    /// Add a generator, replacing any generator already registered with the same name.
+    pub fn register<G: MapGenerator + 'static>(&mut self, name: &str, generator: G) {
+        self.generators.retain(|(existing, _)| existing != name);
+        self.generators
+            .push((name.to_string(), Box::new(generator)));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn MapGenerator> {
+        self.generators
+            .iter()
+            .find(|(existing, _)| existing == name)
+            .map(|(_, generator)| generator.as_ref())
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.generators
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+/// The name of the generator used to build the map.
+#[derive(Clone, Debug)]
+pub struct SelectedGenerator(pub String);
+
+impl SelectedGenerator {
+    /// Use the generator from the environment if one is set, otherwise the default one.
+    pub fn from_env_or_default() -> Self {
+        SelectedGenerator(
+            std::env::var(GENERATOR_ENV_VAR).unwrap_or_else(|_| DEFAULT_GENERATOR.to_string()),
+        )
+    }
+}
+
+pub fn generate(
+    mut commands: Commands,
+    generators: Res<MapGenerators>,
+    selected: Res<SelectedGenerator>,
+    dimensions: Res<MapDimensions>,
+    connectivity_mode: Res<connectivity::ConnectivityMode>,
+    depth: Res<Depth>,
+    mut rng: ResMut<GameRng>,
+) {
+    let generated_map = generate_level(
+        &generators,
+        &selected,
+        &dimensions,
+        *connectivity_mode,
+        *depth,
+        &mut rng,
+    );
+
+    commands.insert_resource(generated_map.world_map);
+    commands.insert_resource(generated_map.layout);
+}
+
+/// Generate a playable level: connected, with stairs leading down and, below the first level,
+/// back up where the player arrives.
+pub fn generate_level(
+    generators: &MapGenerators,
+    selected: &SelectedGenerator,
+    dimensions: &MapDimensions,
+    connectivity_mode: connectivity::ConnectivityMode,
+    depth: Depth,
+    rng: &mut GameRng,
+) -> GeneratedMap {
+    let generator = generators.get(&selected.0).unwrap_or_else(|| {
+        panic!(
+            "Unknown map generator {:?}, expected one of {:?}.",
+            selected.0,
+            generators.names()
+        )
+    });
+
+    let mut generated_map = generator.generate(rng.next_u64(), dimensions.width, dimensions.height);
+    connectivity::connect(&mut generated_map, connectivity_mode);
+    stairs::place_stairs(&mut generated_map, depth.0 > 0);
+    generated_map
+}
+
+/// Carve a horizontal or vertical line of floor between two positions, inclusive.
+pub fn carve_line(start: Position, end: Position, world_map: &mut WorldMap) {
+    for position in line_tiles(start, end) {
+        world_map.set(position.x, position.y, TileKind::Floor);
+    }
+}
+
+/// The tiles of a horizontal or vertical line between two positions, inclusive.
+pub fn line_tiles(start: Position, end: Position) -> Vec<Position> {
+    let mut tiles = Vec::new();
+    for x in start.x.min(end.x)..=start.x.max(end.x) {
+        for y in start.y.min(end.y)..=start.y.max(end.y) {
+            tiles.push(Position { x, y });
+        }
+    }
+    tiles
+}
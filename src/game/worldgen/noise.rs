@@ -0,0 +1,49 @@
+use noise::{utils::*, Fbm, Seedable};
+
+use crate::game::config::{NOISE_SCALE, NOISE_WALL_THRESHOLD};
+use crate::game::map::{TileKind, WorldMap};
+use crate::game::worldgen::{GeneratedMap, MapGenerator};
+
+/// Puts walls wherever a fractal noise function is above a threshold.
+pub struct NoiseGenerator {
+    pub scale: f64,
+    // Between -1 and 1, higher values give fewer walls
+    pub wall_threshold: f64,
+}
+
+impl Default for NoiseGenerator {
+    fn default() -> Self {
+        NoiseGenerator {
+            scale: NOISE_SCALE,
+            wall_threshold: NOISE_WALL_THRESHOLD,
+        }
+    }
+}
+
+impl MapGenerator for NoiseGenerator {
+    fn generate(&self, seed: u64, width: u32, height: u32) -> GeneratedMap {
+        let mut world_map = WorldMap::new(width, height);
+
+        // Construct a noise generator, centred on the middle of the map
+        let noise_function = Fbm::new().set_seed(seed as u32);
+        let half_width = width as f64 / 2.0 / self.scale;
+        let half_height = height as f64 / 2.0 / self.scale;
+        let noise_map = PlaneMapBuilder::new(&noise_function)
+            .set_size(width as usize, height as usize)
+            .set_x_bounds(-half_width, half_width)
+            .set_y_bounds(-half_height, half_height)
+            .build();
+
+        // Use noise function to set walls
+        for i in 0..width {
+            for j in 0..height {
+                let noise_value = noise_map.get_value(i as usize, j as usize);
+                if noise_value > self.wall_threshold {
+                    world_map.set(i, j, TileKind::Wall);
+                }
+            }
+        }
+
+        GeneratedMap::from_world_map(world_map)
+    }
+}
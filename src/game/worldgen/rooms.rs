@@ -0,0 +1,200 @@
+use rand::Rng;
+
+use crate::game::components::Position;
+use crate::game::config::{BSP_MIN_LEAF_SIZE, BSP_MIN_ROOM_SIZE};
+use crate::game::map::{MapLayout, Region, TileKind, WorldMap};
+use crate::game::rng::GameRng;
+use crate::game::worldgen::{carve_line, GeneratedMap, MapGenerator};
+
+/// Carves rectangular rooms joined by L-shaped corridors, laid out with binary space
+/// partitioning.
+///
+/// The player spawns in the first room and every room is its own region.
+pub struct RoomsGenerator {
+    pub min_leaf_size: u32,
+    pub min_room_size: u32,
+}
+
+impl Default for RoomsGenerator {
+    fn default() -> Self {
+        RoomsGenerator {
+            min_leaf_size: BSP_MIN_LEAF_SIZE,
+            min_room_size: BSP_MIN_ROOM_SIZE,
+        }
+    }
+}
+
+impl MapGenerator for RoomsGenerator {
+    fn generate(&self, seed: u64, width: u32, height: u32) -> GeneratedMap {
+        let mut rng = GameRng::from_seed(seed);
+        let mut world_map = WorldMap::new(width, height);
+
+        // Start from solid rock and carve the rooms and corridors out of it
+        for i in 0..width {
+            for j in 0..height {
+                world_map.set(i, j, TileKind::Wall);
+            }
+        }
+
+        let whole_map = Room {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+        let mut rooms = Vec::new();
+        partition(whole_map, self, &mut world_map, &mut rooms, &mut rng);
+
+        GeneratedMap {
+            world_map,
+            layout: MapLayout {
+                spawn_points: rooms.first().map(Room::centre).into_iter().collect(),
+                regions: rooms.iter().map(Room::region).collect(),
+            },
+        }
+    }
+}
+
+/// A rectangular room carved out of the map.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Room {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Room {
+    fn centre(&self) -> Position {
+        Position {
+            x: self.x + self.width / 2,
+            y: self.y + self.height / 2,
+        }
+    }
+
+    fn region(&self) -> Region {
+        let mut tiles = Vec::new();
+        for x in self.x..self.x + self.width {
+            for y in self.y..self.y + self.height {
+                tiles.push(Position { x, y });
+            }
+        }
+        Region { tiles }
+    }
+}
+
+/// Recursively split an area in two until it is too small, then carve a room in each leaf and
+/// join the two halves of every split with a corridor.
+///
+/// Returns the room that corridors into this area should lead to, if the area has any rooms.
+fn partition(
+    area: Room,
+    settings: &RoomsGenerator,
+    world_map: &mut WorldMap,
+    rooms: &mut Vec<Room>,
+    rng: &mut GameRng,
+) -> Option<Room> {
+    let can_split_vertically = area.width >= 2 * settings.min_leaf_size;
+    let can_split_horizontally = area.height >= 2 * settings.min_leaf_size;
+
+    // Prefer cutting across the longer side so the leaves don't end up long and thin
+    let split_vertically = match (can_split_vertically, can_split_horizontally) {
+        (false, false) => return carve_room(area, settings, world_map, rooms, rng),
+        (true, false) => true,
+        (false, true) => false,
+        (true, true) if area.width != area.height => area.width > area.height,
+        (true, true) => rng.gen_bool(0.5),
+    };
+
+    let (first_area, second_area) = if split_vertically {
+        let split = rng.gen_range(settings.min_leaf_size..=area.width - settings.min_leaf_size);
+        (
+            Room {
+                width: split,
+                ..area
+            },
+            Room {
+                x: area.x + split,
+                width: area.width - split,
+                ..area
+            },
+        )
+    } else {
+        let split = rng.gen_range(settings.min_leaf_size..=area.height - settings.min_leaf_size);
+        (
+            Room {
+                height: split,
+                ..area
+            },
+            Room {
+                y: area.y + split,
+                height: area.height - split,
+                ..area
+            },
+        )
+    };
+
+    let first_room = partition(first_area, settings, world_map, rooms, rng);
+    let second_room = partition(second_area, settings, world_map, rooms, rng);
+    if let (Some(first_room), Some(second_room)) = (first_room, second_room) {
+        carve_corridor(first_room, second_room, world_map, rng);
+    }
+
+    first_room.or(second_room)
+}
+
+/// Carve a room somewhere in the area, or nothing if the area is too small to hold one.
+fn carve_room(
+    area: Room,
+    settings: &RoomsGenerator,
+    world_map: &mut WorldMap,
+    rooms: &mut Vec<Room>,
+    rng: &mut GameRng,
+) -> Option<Room> {
+    // Keep a wall between the room and the edge of its area
+    let max_width = area.width.saturating_sub(2);
+    let max_height = area.height.saturating_sub(2);
+    if max_width == 0 || max_height == 0 {
+        return None;
+    }
+    // Rooms in areas smaller than usual, e.g. on a small map, are as big as will fit
+    let width = rng.gen_range(settings.min_room_size.min(max_width)..=max_width);
+    let height = rng.gen_range(settings.min_room_size.min(max_height)..=max_height);
+    let room = Room {
+        x: area.x + 1 + rng.gen_range(0..=area.width - 2 - width),
+        y: area.y + 1 + rng.gen_range(0..=area.height - 2 - height),
+        width,
+        height,
+    };
+
+    for i in room.x..room.x + room.width {
+        for j in room.y..room.y + room.height {
+            world_map.set(i, j, TileKind::Floor);
+        }
+    }
+
+    rooms.push(room);
+    Some(room)
+}
+
+/// Join the centres of two rooms with an L-shaped corridor.
+fn carve_corridor(from: Room, to: Room, world_map: &mut WorldMap, rng: &mut GameRng) {
+    let start = from.centre();
+    let end = to.centre();
+
+    // Randomly choose which leg of the corridor comes first
+    let corner = if rng.gen_bool(0.5) {
+        Position {
+            x: end.x,
+            y: start.y,
+        }
+    } else {
+        Position {
+            x: start.x,
+            y: end.y,
+        }
+    };
+
+    carve_line(start, corner, world_map);
+    carve_line(corner, end, world_map);
+}
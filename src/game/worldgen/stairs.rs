@@ -0,0 +1,60 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::game::components::Position;
+use crate::game::map::{TileKind, WorldMap};
+use crate::game::worldgen::{GeneratedMap, NEIGHBOUR_DELTAS};
+
+/// Put the stairs down as far as possible from the player's spawn, and the stairs up on the
+/// spawn itself if there is a level above.
+///
+/// Must run after the map has been connected, so the stairs can be reached.
+pub fn place_stairs(generated_map: &mut GeneratedMap, has_level_above: bool) {
+    let spawn = *generated_map
+        .layout
+        .spawn_points
+        .first()
+        .expect("The map should have a spawn point.");
+    let world_map = &mut generated_map.world_map;
+
+    let farthest = farthest_tile(world_map, spawn);
+    if has_level_above {
+        world_map.set(spawn.x, spawn.y, TileKind::UpStairs);
+    }
+    if farthest != spawn || !has_level_above {
+        world_map.set(farthest.x, farthest.y, TileKind::DownStairs);
+    } else {
+        log::warn!("The map is too small for stairs down.");
+    }
+}
+
+/// The walkable tile the most steps away from a position, found with a breadth first search.
+fn farthest_tile(world_map: &WorldMap, start: Position) -> Position {
+    let mut visited = vec![false; (world_map.width * world_map.height) as usize];
+    let mut frontier = VecDeque::new();
+    visited[(start.y * world_map.width + start.x) as usize] = true;
+    frontier.push_back(start);
+
+    // The last tile reached is one of the farthest
+    let mut farthest = start;
+    while let Some(position) = frontier.pop_front() {
+        farthest = position;
+        for delta in NEIGHBOUR_DELTAS.iter() {
+            let neighbour = IVec2::new(position.x as i32, position.y as i32) + *delta;
+            if !world_map.is_walkable(neighbour) {
+                continue;
+            }
+            let index = (neighbour.y as u32 * world_map.width + neighbour.x as u32) as usize;
+            if !visited[index] {
+                visited[index] = true;
+                frontier.push_back(Position {
+                    x: neighbour.x as u32,
+                    y: neighbour.y as u32,
+                });
+            }
+        }
+    }
+
+    farthest
+}
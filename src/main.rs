@@ -5,25 +5,104 @@ use bevy::{
 };
 use bevy_ecs_tilemap::prelude::*;
 
+mod cli;
 mod game;
 
+// Set to a number of player turns to simulate the game without a window
+const HEADLESS_TURNS_ENV_VAR: &str = "DINK_HEADLESS_TURNS";
+
 fn main() {
     env_logger::Builder::from_default_env()
         .filter_level(log::LevelFilter::Info)
         .init();
 
-    App::build()
-        .insert_resource(WindowDescriptor {
-            width: (1920 / 2) as f32,
-            height: (1080 / 2) as f32,
-            title: String::from("Roguelike"),
-            ..Default::default()
+    let options = cli::CliOptions::parse(std::env::args().skip(1)).unwrap_or_else(|error| {
+        eprintln!("{}\nRun with --help to see the available options.", error);
+        std::process::exit(2);
+    });
+    if options.help {
+        print!("{}", cli::HELP);
+        return;
+    }
+
+    let mut app = App::build();
+
+    // Read the settings from `--config`, or from the default file if it exists, then let the
+    // command line override them
+    let config = game::settings::GameConfig::load(options.config.as_deref())
+        .and_then(|mut config| {
+            if let Some(map_size) = options.map_size {
+                config.map.width = map_size.width;
+                config.map.height = map_size.height;
+            }
+            if let Some(generator) = &options.generator {
+                config.map.generator = generator.clone();
+            }
+            if let Some((width, height)) = options.window {
+                config.window.width = width as f32;
+                config.window.height = height as f32;
+            }
+            config.validate()?;
+            Ok(config)
         })
-        .add_plugins(DefaultPlugins)
-        .add_system(exit_on_esc_system.system())
-        .add_plugin(LogDiagnosticsPlugin::default())
-        .add_plugin(FrameTimeDiagnosticsPlugin::default())
-        .add_plugin(TilemapPlugin)
-        .add_plugin(game::GamePlugin)
-        .run();
+        .unwrap_or_else(|error| {
+            log::error!("{}", error);
+            std::process::exit(1);
+        });
+    config.insert_resources(&mut app);
+
+    if let Some(seed) = options.seed {
+        app.insert_resource(game::rng::GameRng::from_seed(seed));
+    }
+
+    if let Some(path) = &options.load {
+        app.insert_resource(game::save::LoadOnStartup(path.clone()));
+    }
+
+    if let Some(path) = &options.replay {
+        let replay = game::replay::Replay::read(path).unwrap_or_else(|error| {
+            log::error!("Couldn't read the replay {}: {}", path.display(), error);
+            std::process::exit(1);
+        });
+        // The seed only reproduces the run with the settings it was recorded with
+        let settings = game::replay::ReplaySettings::from_world(app.world());
+        if let Err(error) = replay.check_settings(&settings) {
+            log::error!(
+                "Couldn't play back the replay {}: {}",
+                path.display(),
+                error
+            );
+            std::process::exit(1);
+        }
+        app.insert_resource(game::rng::GameRng::from_seed(replay.seed))
+            .insert_resource(game::replay::ReplayPlayback::new(replay));
+    }
+
+    let headless_turns = options.headless_turns.or_else(|| {
+        std::env::var(HEADLESS_TURNS_ENV_VAR).ok().map(|turns| {
+            turns.parse().unwrap_or_else(|_| {
+                panic!("{} must be an unsigned integer.", HEADLESS_TURNS_ENV_VAR)
+            })
+        })
+    });
+
+    match headless_turns {
+        Some(turns) => {
+            app.add_plugins(MinimalPlugins)
+                .add_plugin(game::GamePlugin)
+                .add_plugin(game::headless::HeadlessPlugin { turns })
+                .run();
+        }
+        None => {
+            app.insert_resource(config.window_descriptor())
+                .add_plugins(DefaultPlugins)
+                .add_system(exit_on_esc_system.system())
+                .add_plugin(LogDiagnosticsPlugin::default())
+                .add_plugin(FrameTimeDiagnosticsPlugin::default())
+                .add_plugin(TilemapPlugin)
+                .add_plugin(game::GamePlugin)
+                .add_plugin(game::GraphicsPlugin)
+                .run();
+        }
+    }
 }
@@ -0,0 +1,180 @@
+use std::path::PathBuf;
+
+use bevy::app::{AppExit, Events};
+use bevy::prelude::*;
+use rand::RngCore;
+
+use dink::game::components::{Health, Position};
+use dink::game::headless::HeadlessPlugin;
+use dink::game::levels::Depth;
+use dink::game::map::MapDimensions;
+use dink::game::replay::{Replay, ReplayPlayback, ReplayRecorder, ReplaySettings};
+use dink::game::rng::GameRng;
+use dink::game::states::{GameState, TurnCount};
+use dink::game::worldgen::SelectedGenerator;
+use dink::game::GamePlugin;
+
+const SEED: u64 = 42;
+const TURNS: u32 = 50;
+// Give up on a run that doesn't exit, rather than hanging the test
+const MAX_UPDATES: usize = 100_000;
+
+/// Build a headless app that doesn't read anything from the environment.
+fn headless_app(seed: u64, playback: Option<Replay>) -> AppBuilder {
+    let mut app = App::build();
+    app.insert_resource(GameRng::from_seed(seed))
+        .insert_resource(MapDimensions::default())
+        .insert_resource(SelectedGenerator("caves".to_string()));
+    if let Some(replay) = playback {
+        app.insert_resource(ReplayPlayback::new(replay));
+    }
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(GamePlugin)
+        .add_plugin(HeadlessPlugin { turns: TURNS });
+    app
+}
+
+/// Update the app until it asks to exit.
+fn run_until_exit(app: &mut AppBuilder) {
+    let mut app_exit_reader = app
+        .world()
+        .get_resource::<Events<AppExit>>()
+        .unwrap()
+        .get_reader();
+    for _ in 0..MAX_UPDATES {
+        app.app.update();
+        let app_exit_events = app.world().get_resource::<Events<AppExit>>().unwrap();
+        if app_exit_reader.iter(app_exit_events).next().is_some() {
+            return;
+        }
+    }
+    panic!("The app didn't exit after {} updates.", MAX_UPDATES);
+}
+
+/// The parts of the game that show whether two runs went the same way.
+#[derive(Debug, PartialEq)]
+struct Snapshot {
+    turn_count: u32,
+    depth: u32,
+    entities: Vec<(Position, Option<i32>)>,
+    next_random_number: u64,
+}
+
+impl Snapshot {
+    fn take(app: &mut AppBuilder) -> Self {
+        let world = &mut app.app.world;
+        let mut entities: Vec<_> = world
+            .query::<(&Position, Option<&Health>)>()
+            .iter(world)
+            .map(|(position, health)| (*position, health.map(|health| health.current)))
+            .collect();
+        entities.sort_unstable();
+
+        Snapshot {
+            turn_count: world.get_resource::<TurnCount>().unwrap().0,
+            depth: world.get_resource::<Depth>().unwrap().0,
+            entities,
+            // Drawing from a copy shows whether the generators are in the same state
+            next_random_number: world.get_resource::<GameRng>().unwrap().clone().next_u64(),
+        }
+    }
+}
+
+/// A replay file in the temporary directory, removed once the test is done with it.
+struct TempReplayFile(PathBuf);
+
+impl TempReplayFile {
+    fn new(name: &str) -> Self {
+        TempReplayFile(std::env::temp_dir().join(format!(
+            "dink-{}-{}.replay.ron",
+            name,
+            std::process::id()
+        )))
+    }
+}
+
+impl Drop for TempReplayFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Record the run to the given file instead of the default replay path.
+fn record_to(app: &mut AppBuilder, replay_file: &TempReplayFile) {
+    let settings = ReplaySettings::from_world(app.world());
+    app.insert_resource(ReplayRecorder {
+        path: replay_file.0.clone(),
+        replay: Replay::new(SEED, settings),
+        is_recording: true,
+    });
+}
+
+#[test]
+fn headless_runs_stop_at_the_turn_limit() {
+    let replay_file = TempReplayFile::new("turn-limit");
+    let mut app = headless_app(SEED, None);
+    record_to(&mut app, &replay_file);
+    run_until_exit(&mut app);
+
+    let turn_count = app.world().get_resource::<TurnCount>().unwrap().0;
+    let game_state = app.world().get_resource::<State<GameState>>().unwrap();
+    // The run only ends early if the player dies
+    assert!(turn_count == TURNS || game_state.current() == &GameState::GameOver);
+    assert!(turn_count <= TURNS);
+}
+
+#[test]
+fn headless_runs_with_the_same_seed_are_the_same() {
+    let first_file = TempReplayFile::new("same-seed-first");
+    let mut first_app = headless_app(SEED, None);
+    record_to(&mut first_app, &first_file);
+    run_until_exit(&mut first_app);
+
+    let second_file = TempReplayFile::new("same-seed-second");
+    let mut second_app = headless_app(SEED, None);
+    record_to(&mut second_app, &second_file);
+    run_until_exit(&mut second_app);
+
+    assert_eq!(
+        Snapshot::take(&mut first_app),
+        Snapshot::take(&mut second_app)
+    );
+}
+
+#[test]
+fn replays_end_in_the_same_state_as_the_recorded_run() {
+    let replay_file = TempReplayFile::new("replay");
+    let mut recorded_app = headless_app(SEED, None);
+    record_to(&mut recorded_app, &replay_file);
+    run_until_exit(&mut recorded_app);
+    let recorded = Snapshot::take(&mut recorded_app);
+
+    // The replay is written out when the app exits
+    let replay = Replay::read(&replay_file.0).unwrap();
+    assert_eq!(replay.actions.len() as u32, recorded.turn_count);
+
+    let mut replayed_app = headless_app(replay.seed, Some(replay));
+    run_until_exit(&mut replayed_app);
+
+    assert_eq!(recorded, Snapshot::take(&mut replayed_app));
+}
+
+#[test]
+fn replays_need_the_settings_they_were_recorded_with() {
+    let mut app = headless_app(SEED, None);
+    let settings = ReplaySettings::from_world(app.world());
+    let replay = Replay::new(SEED, settings.clone());
+    assert!(replay.check_settings(&settings).is_ok());
+
+    let mut other_size = settings.clone();
+    other_size.map_dimensions = MapDimensions::new(40, 30);
+    assert!(replay.check_settings(&other_size).is_err());
+
+    let mut other_generator = settings.clone();
+    other_generator.generator = "rooms".to_string();
+    assert!(replay.check_settings(&other_generator).is_err());
+
+    let mut other_worldgen = settings;
+    other_worldgen.worldgen.cave_fill_probability /= 2.0;
+    assert!(replay.check_settings(&other_worldgen).is_err());
+}